@@ -0,0 +1,891 @@
+use crate::digest::{HashingWriter, DIGEST_LEN};
+use crate::filename;
+use filetime::FileTime;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_tar::{ArchiveBuilder, EntryType, Header, HeaderMode};
+
+/// The suffix appended to the name of an offered archive, to tell it apart from a regular file.
+pub const ARCHIVE_SUFFIX: &str = ".pylon.tar";
+
+/// The name of the manifest, which is always the first entry of an archive.
+const MANIFEST_NAME: &str = ".pylon-manifest.json";
+
+/// The name of the digest, which is always the last entry of an archive.
+const DIGEST_NAME: &str = ".pylon-digest";
+
+/// The largest manifest we're willing to read.
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+/// The size of a tar block.
+const BLOCK_SIZE: u64 = 512;
+
+/// The length of the name fields in a tar header.
+const NAME_FIELD_LEN: usize = 100;
+
+/// The number of bytes that follow the data covered by an archive's digest: the digest entry,
+/// and the two empty blocks that end the archive.
+pub const TRAILER_SIZE: u64 = 4 * BLOCK_SIZE;
+
+/// Describes the contents of an archive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Checks that every name is a plain, unique file name, and sanitizes it.
+    ///
+    /// Manifests come straight from the peer, so this must be done before using any of them.
+    pub fn validate(&mut self) -> io::Result<()> {
+        let mut names = HashSet::new();
+        for entry in &mut self.entries {
+            if !is_plain_name(Path::new(&entry.name)) {
+                return Err(invalid_data("invalid manifest"));
+            }
+
+            entry.name = filename::sanitize(&entry.name);
+            if !names.insert(entry.name.clone()) {
+                return Err(invalid_data("invalid manifest"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A top-level file or folder in an archive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub name: String,
+    pub is_folder: bool,
+    /// The total size of the files in this entry.
+    pub size: u64,
+    /// The number of files in this entry.
+    pub file_count: u64,
+}
+
+/// The kind of an entry in an archive.
+enum EntryKind {
+    Directory,
+    File,
+    Symlink(PathBuf),
+}
+
+/// An entry that is about to be archived.
+struct Entry {
+    /// The path of the entry on disk.
+    path: PathBuf,
+    /// The path of the entry in the archive, starting with its top-level name.
+    name: PathBuf,
+    kind: EntryKind,
+    metadata: std::fs::Metadata,
+}
+
+impl Entry {
+    /// The number of bytes this entry takes up in the archive.
+    fn archived_size(&self) -> u64 {
+        let mut size = BLOCK_SIZE + long_name_size(&tar_path(&self.name));
+        match &self.kind {
+            EntryKind::File => size += padded(self.metadata.len()),
+            EntryKind::Symlink(target) => size += long_name_size(&tar_path(target)),
+            EntryKind::Directory => {}
+        }
+
+        size
+    }
+}
+
+/// A set of files and folders, walked and ready to be streamed as a tar archive.
+pub struct Bundle {
+    contents: Manifest,
+    manifest: Vec<u8>,
+    entries: Vec<Entry>,
+    size: u64,
+}
+
+impl Bundle {
+    /// Walks the files and folders at the given paths.
+    ///
+    /// Each path ends up at the top level of the archive, under its own name. The paths were
+    /// picked explicitly, so those that are symlinks are followed, and anything other than a file
+    /// or folder is refused. Within folders, symlinks are archived as links rather than followed.
+    /// Symlinks that are absolute or that point outside of their top-level folder are skipped, so
+    /// that nothing else is ever sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The paths of the files and folders to walk.
+    pub async fn walk(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut manifest = Manifest {
+            entries: Vec::with_capacity(paths.len()),
+        };
+        let mut entries = Vec::new();
+
+        for root in paths {
+            let root_name = root.file_name().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "path has no file name")
+            })?;
+            let name = root_name.to_string_lossy().into_owned();
+            if manifest.entries.iter().any(|entry| entry.name == name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("more than one file or folder is named {name}"),
+                ));
+            }
+
+            let first = entries.len();
+            walk_into(&mut entries, root, PathBuf::from(root_name)).await?;
+
+            let files = entries[first..]
+                .iter()
+                .filter(|entry| matches!(entry.kind, EntryKind::File));
+            manifest.entries.push(ManifestEntry {
+                name,
+                is_folder: matches!(entries[first].kind, EntryKind::Directory),
+                size: files.clone().map(|entry| entry.metadata.len()).sum(),
+                file_count: files.count() as u64,
+            });
+        }
+
+        let contents = manifest;
+        let manifest = serde_json::to_vec(&contents)?;
+        let size = BLOCK_SIZE
+            + padded(manifest.len() as u64)
+            + entries.iter().map(Entry::archived_size).sum::<u64>()
+            + TRAILER_SIZE;
+
+        Ok(Self {
+            contents,
+            manifest,
+            entries,
+            size,
+        })
+    }
+
+    /// Describes the top-level files and folders in the bundle.
+    pub fn contents(&self) -> &Manifest {
+        &self.contents
+    }
+
+    /// The exact number of bytes the archive will take up.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Streams the bundle as a tar archive to the given writer.
+    ///
+    /// As long as the files and folders haven't changed, the archive is the same every time.
+    ///
+    /// The archive ends with the SHA-256 digest of everything before it, so that the receiving
+    /// end can check that nothing was corrupted along the way.
+    ///
+    /// Returns the hex-encoded digest.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The writer to stream the archive to.
+    pub async fn write_to<W>(&self, writer: W) -> io::Result<String>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut writer = HashingWriter::new(writer);
+        write_metadata_entry(&mut writer, MANIFEST_NAME, &self.manifest).await?;
+
+        for entry in &self.entries {
+            let mut header = Header::new_gnu();
+            header.set_metadata_in_mode(&entry.metadata, HeaderMode::Complete);
+
+            write_name(
+                &mut writer,
+                &mut header,
+                &entry.name,
+                EntryType::GNULongName,
+            )
+            .await?;
+            match &entry.kind {
+                EntryKind::Directory => {
+                    header.set_entry_type(EntryType::Directory);
+                    header.set_size(0);
+                }
+                EntryKind::File => {
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_size(entry.metadata.len());
+                }
+                EntryKind::Symlink(target) => {
+                    header.set_entry_type(EntryType::Symlink);
+                    header.set_size(0);
+                    write_name(&mut writer, &mut header, target, EntryType::GNULongLink).await?;
+                }
+            }
+            header.set_cksum();
+            writer.write_all(header.as_bytes()).await?;
+
+            if let EntryKind::File = &entry.kind {
+                // The file may have changed since we walked it, but the header has already
+                // promised a size, so hold it to that.
+                let size = entry.metadata.len();
+                let file = fs::File::open(&entry.path).await?;
+                let copied = tokio::io::copy(&mut file.take(size), &mut writer).await?;
+                if copied != size {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("{} shrank while being sent", entry.path.display()),
+                    ));
+                }
+                write_padding(&mut writer, size).await?;
+            }
+        }
+
+        let (mut writer, digest) = writer.finish();
+        write_metadata_entry(&mut writer, DIGEST_NAME, digest.as_bytes()).await?;
+
+        // An archive ends with two empty blocks.
+        writer.write_all(&[0; 2 * BLOCK_SIZE as usize]).await?;
+        writer.flush().await?;
+
+        Ok(digest)
+    }
+}
+
+/// Writes an entry of our own, such as the manifest, to an archive.
+async fn write_metadata_entry<W>(writer: &mut W, name: &str, data: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut header = Header::new_gnu();
+    header.set_path(name)?;
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    // Archives must come out the same every time, so that a transfer can be resumed.
+    header.set_mtime(0);
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    writer.write_all(header.as_bytes()).await?;
+    writer.write_all(data).await?;
+    write_padding(writer, data.len() as u64).await
+}
+
+/// Walks the file or folder at `root`, appending its entries under the given archive name.
+///
+/// `root` itself is followed if it's a symlink, and must be a file or folder.
+async fn walk_into(entries: &mut Vec<Entry>, root: &Path, root_name: PathBuf) -> io::Result<()> {
+    let metadata = fs::metadata(root).await?;
+    if !metadata.is_dir() && !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is neither a file nor a folder", root.display()),
+        ));
+    }
+
+    let mut pending = vec![(root.to_path_buf(), root_name, Some(metadata))];
+    while let Some((path, name, metadata)) = pending.pop() {
+        let metadata = match metadata {
+            Some(metadata) => metadata,
+            None => fs::symlink_metadata(&path).await?,
+        };
+        let file_type = metadata.file_type();
+
+        let kind = if file_type.is_dir() {
+            let mut children = Vec::new();
+            let mut dir = fs::read_dir(&path).await?;
+            while let Some(child) = dir.next_entry().await? {
+                children.push((child.path(), name.join(child.file_name())));
+            }
+
+            // Walk children in order, which means pushing them in reverse.
+            children.sort();
+            pending.extend(
+                children
+                    .into_iter()
+                    .rev()
+                    .map(|(path, name)| (path, name, None)),
+            );
+
+            EntryKind::Directory
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(&path).await?;
+            let parent = name.parent().unwrap_or(Path::new(""));
+            if !stays_inside(parent, &target) {
+                continue;
+            }
+
+            EntryKind::Symlink(target)
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            // Sockets, devices and the like have no business being sent.
+            continue;
+        };
+
+        entries.push(Entry {
+            path,
+            name,
+            kind,
+            metadata,
+        });
+    }
+
+    Ok(())
+}
+
+/// Reads the manifest at the start of an archive streamed from the given reader.
+///
+/// The rest of the archive can then be unpacked with [`unpack`].
+///
+/// # Arguments
+///
+/// * `reader` - The reader to stream the archive from.
+pub async fn read_manifest<R>(reader: &mut R) -> io::Result<Manifest>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut block = [0; BLOCK_SIZE as usize];
+    reader.read_exact(&mut block).await?;
+
+    let header = Header::from_byte_slice(&block);
+    let size = header.size()?;
+    if header.path_bytes().as_ref() != MANIFEST_NAME.as_bytes() || size > MAX_MANIFEST_SIZE {
+        return Err(invalid_data("archive has no manifest"));
+    }
+
+    let mut data = vec![0; padded(size) as usize];
+    reader.read_exact(&mut data).await?;
+    data.truncate(size as usize);
+    let mut manifest: Manifest = serde_json::from_slice(&data)?;
+    manifest.validate()?;
+
+    Ok(manifest)
+}
+
+/// Unpacks the rest of a tar archive streamed from the given reader.
+///
+/// Each top-level file or folder is unpacked to the path it maps to, within the destination
+/// directory, or skipped if it maps to `None`. Permissions and modification times are
+/// preserved, those of folders only once everything in them has been unpacked. Names are
+/// sanitized like those in the manifest. Entries that aren't listed in the archive's manifest,
+/// or that would end up outside of the destination directory, including through symlinks, are
+/// rejected.
+///
+/// Returns the digest found at the end of the archive, which it's up to the caller to check.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to stream the archive from, just past its manifest.
+/// * `destination_dir` - The directory to unpack the archive into.
+/// * `targets` - Where to unpack each top-level file or folder listed in the manifest.
+pub async fn unpack<R>(
+    mut reader: R,
+    destination_dir: &Path,
+    targets: &HashMap<String, Option<PathBuf>>,
+) -> io::Result<String>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut digest = None;
+    let mut dirs = Vec::new();
+
+    {
+        let mut archive = ArchiveBuilder::new(&mut reader)
+            .set_preserve_permissions(true)
+            .set_preserve_mtime(true)
+            .set_unpack_xattrs(false)
+            .build();
+        let mut entries = archive.entries()?;
+        let destination = fs::canonicalize(destination_dir).await?;
+
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry?;
+            let name = entry.path()?.into_owned();
+
+            // Nothing may follow the digest, as it wouldn't be covered by it.
+            if digest.is_some() {
+                return Err(invalid_entry(&name));
+            }
+            if name == Path::new(DIGEST_NAME) {
+                let mut data = String::with_capacity(DIGEST_LEN);
+                (&mut entry)
+                    .take(DIGEST_LEN as u64)
+                    .read_to_string(&mut data)
+                    .await?;
+                digest = Some(data);
+                continue;
+            }
+
+            if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(invalid_entry(&name));
+            }
+
+            let mut components = name
+                .components()
+                .map(|c| filename::sanitize(&c.as_os_str().to_string_lossy()));
+            let top_level = components.next().unwrap_or_default();
+            let Some(target) = targets.get(&top_level) else {
+                return Err(invalid_entry(&name));
+            };
+            // Skipped entries are left unread, which the archive takes care of.
+            let Some(target) = target else {
+                continue;
+            };
+
+            match entry.header().entry_type() {
+                EntryType::Regular | EntryType::Directory => {}
+                EntryType::Symlink => {
+                    let link = entry.link_name()?.ok_or_else(|| invalid_entry(&name))?;
+                    if !stays_inside(name.parent().unwrap_or(Path::new("")), &link) {
+                        return Err(invalid_entry(&name));
+                    }
+                }
+                // Hard links in particular could point anywhere on the receiving end.
+                _ => return Err(invalid_entry(&name)),
+            }
+
+            let rest: PathBuf = components.collect();
+            let target = if rest.as_os_str().is_empty() {
+                target.clone()
+            } else {
+                target.join(rest)
+            };
+
+            // Something that already exists on our end could still lead elsewhere.
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).await?;
+                if !fs::canonicalize(parent).await?.starts_with(&destination) {
+                    return Err(invalid_entry(&name));
+                }
+            }
+
+            if entry.header().entry_type() == EntryType::Directory {
+                fs::create_dir_all(&target).await?;
+                dirs.push(UnpackedDir {
+                    path: target,
+                    mode: entry.header().mode()?,
+                    mtime: entry.header().mtime()?,
+                });
+            } else {
+                entry.unpack(&target).await?;
+            }
+        }
+    }
+
+    // Children first, so that a read-only directory doesn't keep those in it from being finished.
+    dirs.sort_by(|a, b| b.path.cmp(&a.path));
+    tokio::task::spawn_blocking(move || dirs.iter().try_for_each(UnpackedDir::finish))
+        .await
+        .map_err(io::Error::other)??;
+
+    // The sender may still be writing trailing blocks, which we have no use for.
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+
+    digest.ok_or_else(|| invalid_data("archive has no digest"))
+}
+
+/// Indicates if the path consists of a single, plain file name.
+fn is_plain_name(path: &Path) -> bool {
+    let mut components = path.components();
+    matches!(components.next(), Some(Component::Normal(_))) && components.next().is_none()
+}
+
+/// Indicates if a link at `base`, pointing at `target`, resolves to somewhere inside of the
+/// top-level directory of `base`. Top-level links never do.
+///
+/// This is purely lexical, which is what we want: the archive must be safe no matter what
+/// already exists on the receiving end.
+fn stays_inside(base: &Path, target: &Path) -> bool {
+    if base.as_os_str().is_empty() {
+        return false;
+    }
+
+    let mut depth = 0usize;
+    for component in base.join(target).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 1 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+/// A directory that has been unpacked, but whose permissions and modification time have yet to
+/// be applied.
+struct UnpackedDir {
+    path: PathBuf,
+    mode: u32,
+    mtime: u64,
+}
+
+impl UnpackedDir {
+    /// Applies the directory's permissions and modification time.
+    ///
+    /// This is done last, like tar does, so that writing into the directory neither fails because
+    /// it's read-only, nor changes its modification time.
+    fn finish(&self) -> io::Result<()> {
+        filetime::set_file_mtime(&self.path, FileTime::from_unix_time(self.mtime as i64, 0))?;
+
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::Permissions::from_mode(self.mode & 0o777)
+        };
+        #[cfg(not(unix))]
+        let permissions = {
+            let mut permissions = std::fs::metadata(&self.path)?.permissions();
+            permissions.set_readonly(self.mode & 0o200 == 0);
+            permissions
+        };
+
+        std::fs::set_permissions(&self.path, permissions)
+    }
+}
+
+/// Formats a path as it's stored in a tar archive.
+fn tar_path(path: &Path) -> Vec<u8> {
+    let components: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().as_encoded_bytes())
+        .collect();
+
+    components.join(&b'/')
+}
+
+/// Writes a name into the header, preceded by a GNU long name entry if it doesn't fit.
+async fn write_name<W>(
+    writer: &mut W,
+    header: &mut Header,
+    name: &Path,
+    long_name_type: EntryType,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let bytes = tar_path(name);
+    let field = match long_name_type {
+        EntryType::GNULongLink => &mut header.as_old_mut().linkname,
+        _ => &mut header.as_old_mut().name,
+    };
+    let len = bytes.len().min(NAME_FIELD_LEN);
+    field.fill(0);
+    field[..len].copy_from_slice(&bytes[..len]);
+
+    if bytes.len() >= NAME_FIELD_LEN {
+        let mut long_header = Header::new_gnu();
+        long_header.as_old_mut().name[..13].copy_from_slice(b"././@LongLink");
+        long_header.set_entry_type(long_name_type);
+        long_header.set_mode(0o644);
+        long_header.set_size(bytes.len() as u64 + 1);
+        long_header.set_cksum();
+
+        writer.write_all(long_header.as_bytes()).await?;
+        writer.write_all(&bytes).await?;
+        writer.write_all(&[0]).await?;
+        write_padding(writer, bytes.len() as u64 + 1).await?;
+    }
+
+    Ok(())
+}
+
+/// Pads data of the given length up to the next block boundary.
+async fn write_padding<W>(writer: &mut W, len: u64) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let padding = (padded(len) - len) as usize;
+    writer.write_all(&[0; BLOCK_SIZE as usize][..padding]).await
+}
+
+/// The number of bytes a GNU long name entry takes up, if one is needed for the given name.
+fn long_name_size(name: &[u8]) -> u64 {
+    if name.len() >= NAME_FIELD_LEN {
+        BLOCK_SIZE + padded(name.len() as u64 + 1)
+    } else {
+        0
+    }
+}
+
+/// Rounds the given length up to the next block boundary.
+fn padded(len: u64) -> u64 {
+    len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_entry(name: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("refusing to unpack {}", name.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Formats the start of an archive, up to and including a manifest with the given contents.
+    async fn archive_with_manifest(name: &str, manifest: serde_json::Value) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let data = serde_json::to_vec(&manifest).unwrap();
+        write_metadata_entry(&mut archive, name, &data)
+            .await
+            .unwrap();
+
+        archive
+    }
+
+    /// Formats a manifest listing files with the given names.
+    fn manifest_of(names: &[&str]) -> serde_json::Value {
+        let entries: Vec<_> = names
+            .iter()
+            .map(|name| json!({ "name": name, "isFolder": false, "size": 1, "fileCount": 1 }))
+            .collect();
+
+        json!({ "entries": entries })
+    }
+
+    async fn read(archive: Vec<u8>) -> io::Result<Manifest> {
+        read_manifest(&mut archive.as_slice()).await
+    }
+
+    #[test]
+    fn links_inside_their_folder_stay_inside() {
+        assert!(stays_inside(Path::new("top"), Path::new("file")));
+        assert!(stays_inside(Path::new("top"), Path::new("./sub/file")));
+        assert!(stays_inside(Path::new("top/sub"), Path::new("../file")));
+        assert!(stays_inside(
+            Path::new("top/sub"),
+            Path::new("other/../../file")
+        ));
+    }
+
+    #[test]
+    fn links_out_of_their_folder_dont_stay_inside() {
+        assert!(!stays_inside(Path::new("top"), Path::new("..")));
+        assert!(!stays_inside(Path::new("top"), Path::new("../top/file")));
+        assert!(!stays_inside(Path::new("top/sub"), Path::new("../../file")));
+        assert!(!stays_inside(
+            Path::new("top/sub"),
+            Path::new("a/../../../file")
+        ));
+        assert!(!stays_inside(Path::new("top"), Path::new("/etc/passwd")));
+    }
+
+    #[test]
+    fn top_level_links_never_stay_inside() {
+        assert!(!stays_inside(Path::new(""), Path::new("file")));
+        assert!(!stays_inside(Path::new(""), Path::new(".")));
+    }
+
+    #[tokio::test]
+    async fn reads_the_manifest() {
+        let archive = archive_with_manifest(MANIFEST_NAME, manifest_of(&["a.txt", "b"])).await;
+        let manifest = read(archive).await.unwrap();
+
+        let names: Vec<_> = manifest.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "b"]);
+    }
+
+    #[tokio::test]
+    async fn sanitizes_manifest_names() {
+        let archive = archive_with_manifest(MANIFEST_NAME, manifest_of(&["CON.txt", "a:b"])).await;
+        let manifest = read(archive).await.unwrap();
+
+        let names: Vec<_> = manifest.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["_CON.txt", "a_b"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_manifests_with_non_plain_names() {
+        for name in ["", ".", "..", "a/b", "a/../b", "/etc/passwd", "./a"] {
+            let archive = archive_with_manifest(MANIFEST_NAME, manifest_of(&[name])).await;
+            let err = read(archive).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{name:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_manifests_with_names_that_collide_once_sanitized() {
+        let archive = archive_with_manifest(MANIFEST_NAME, manifest_of(&["a:b", "a_b"])).await;
+        let err = read(archive).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn rejects_archives_without_a_manifest() {
+        let archive = archive_with_manifest("manifest.json", manifest_of(&["a.txt"])).await;
+        let err = read(archive).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = read(Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unpacks_folders_with_their_permissions_and_times() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let mtime = |path: &Path| {
+            FileTime::from_last_modification_time(&std::fs::metadata(path).unwrap()).unix_seconds()
+        };
+        let chmod = |path: &Path, mode: u32| {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap()
+        };
+
+        let source = tempfile::tempdir().unwrap();
+        let top = source.path().join("top");
+        std::fs::create_dir_all(top.join("sub")).unwrap();
+        std::fs::create_dir(top.join("empty")).unwrap();
+        std::fs::write(top.join("file.txt"), "file").unwrap();
+        std::fs::write(top.join("sub").join("inner.txt"), "inner").unwrap();
+        for (dir, mode, mtime) in [
+            (top.join("sub"), 0o700, 1_000_000),
+            (top.join("empty"), 0o500, 2_000_000),
+            (top.clone(), 0o555, 3_000_000),
+        ] {
+            filetime::set_file_mtime(&dir, FileTime::from_unix_time(mtime, 0)).unwrap();
+            chmod(&dir, mode);
+        }
+
+        let bundle = Bundle::walk(std::slice::from_ref(&top)).await.unwrap();
+        let mut archive = Vec::new();
+        let sent = bundle.write_to(&mut archive).await.unwrap();
+
+        let destination = tempfile::tempdir().unwrap();
+        let target = destination.path().join("top");
+        let targets = HashMap::from([("top".to_string(), Some(target.clone()))]);
+        let mut reader = archive.as_slice();
+        read_manifest(&mut reader).await.unwrap();
+        let received = unpack(reader, destination.path(), &targets).await.unwrap();
+
+        assert_eq!(received, sent);
+        assert_eq!(
+            std::fs::read_to_string(target.join("sub/inner.txt")).unwrap(),
+            "inner"
+        );
+        assert_eq!(
+            (mode(&target.join("sub")), mtime(&target.join("sub"))),
+            (0o700, 1_000_000)
+        );
+        assert_eq!(
+            (mode(&target.join("empty")), mtime(&target.join("empty"))),
+            (0o500, 2_000_000)
+        );
+        assert_eq!((mode(&target), mtime(&target)), (0o555, 3_000_000));
+
+        // Let the temporary directories be removed.
+        for dir in [&top, &top.join("empty"), &target, &target.join("empty")] {
+            chmod(dir, 0o755);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn follows_top_level_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("folder")).unwrap();
+        std::fs::write(dir.path().join("folder").join("inner.txt"), "inner").unwrap();
+        std::fs::write(dir.path().join("file.txt"), "file").unwrap();
+        std::os::unix::fs::symlink("file.txt", dir.path().join("file-link")).unwrap();
+        std::os::unix::fs::symlink("folder", dir.path().join("folder-link")).unwrap();
+
+        let paths = [dir.path().join("file-link"), dir.path().join("folder-link")];
+        let bundle = Bundle::walk(&paths).await.unwrap();
+
+        let entries: Vec<_> = bundle
+            .contents()
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.is_folder, e.size, e.file_count))
+            .collect();
+        assert_eq!(
+            entries,
+            [("file-link", false, 4, 1), ("folder-link", true, 5, 1)]
+        );
+
+        let mut archive = Vec::new();
+        bundle.write_to(&mut archive).await.unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        let targets = HashMap::from([
+            ("file-link".to_string(), Some(destination.path().join("a"))),
+            (
+                "folder-link".to_string(),
+                Some(destination.path().join("b")),
+            ),
+        ]);
+        let mut reader = archive.as_slice();
+        read_manifest(&mut reader).await.unwrap();
+        unpack(reader, destination.path(), &targets).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(destination.path().join("a")).unwrap(),
+            "file"
+        );
+        let inner = destination.path().join("b").join("inner.txt");
+        assert_eq!(std::fs::read_to_string(inner).unwrap(), "inner");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn refuses_top_level_special_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("socket");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        let err = Bundle::walk(&[socket]).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn refuses_dangling_top_level_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink("missing", &link).unwrap();
+
+        let err = Bundle::walk(&[link]).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn skips_special_files_and_escaping_links_within_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let top = dir.path().join("top");
+        std::fs::create_dir(&top).unwrap();
+        std::fs::write(top.join("file.txt"), "file").unwrap();
+        std::os::unix::fs::symlink("file.txt", top.join("inside")).unwrap();
+        std::os::unix::fs::symlink("../outside", top.join("outside")).unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", top.join("absolute")).unwrap();
+        let _listener = std::os::unix::net::UnixListener::bind(top.join("socket")).unwrap();
+
+        let bundle = Bundle::walk(&[top]).await.unwrap();
+
+        let names: Vec<_> = bundle.entries.iter().map(|e| e.name.clone()).collect();
+        let expected = ["top", "top/file.txt", "top/inside"].map(PathBuf::from);
+        assert_eq!(names, expected);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_manifests() {
+        let mut header = Header::new_gnu();
+        header.set_path(MANIFEST_NAME).unwrap();
+        header.set_size(MAX_MANIFEST_SIZE + 1);
+        header.set_cksum();
+
+        let err = read(header.as_bytes().to_vec()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
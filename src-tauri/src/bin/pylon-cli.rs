@@ -0,0 +1,501 @@
+//! A command-line client, for exchanging files with Pylon from machines without a display.
+//!
+//! It shares the desktop app's settings, history and partially received transfers.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use pylon_desktop::collision::Plan;
+use pylon_desktop::error::Error;
+use pylon_desktop::history::{HistoryStore, TransferRecord, HISTORY_FILE_NAME};
+use pylon_desktop::progress::Direction;
+use pylon_desktop::pylon::Pylon;
+use pylon_desktop::resume::{ResumeStore, RESUME_DIR_NAME};
+use pylon_desktop::settings::{CollisionPolicy, Settings, SettingsStore, SETTINGS_FILE_NAME};
+use pylon_desktop::transfer::{Connected, Incoming, Offer, Outgoing, MAX_MESSAGE_SIZE};
+use pylon_desktop::{code, transfer, APP_ID};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, ExitCode};
+use tokio_util::sync::CancellationToken;
+
+/// The template of the progress bar shown during transfers.
+const PROGRESS_TEMPLATE: &str =
+    "{wide_bar} {bytes}/{total_bytes} ({binary_bytes_per_sec}, {eta} left)";
+
+#[derive(Parser)]
+#[command(
+    name = "pylon-cli",
+    version,
+    about = "Send and receive files with Pylon"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generates a Pylon code, then sends what's read from stdin with it.
+    ///
+    /// The code can be handed out while what to send is still being decided, as in the desktop
+    /// app: the paths of files and folders, one per line, or a message.
+    GenCode {
+        /// The number of words in the code. Defaults to the one in the settings.
+        #[arg(short, long)]
+        length: Option<usize>,
+        /// Reads a message to send, rather than paths.
+        #[arg(long)]
+        text: bool,
+    },
+    /// Sends files and folders, printing the code to receive them with.
+    Send {
+        /// The files and folders to send.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// The number of words in the code. Defaults to the one in the settings.
+        #[arg(short, long)]
+        length: Option<usize>,
+    },
+    /// Sends a short message, printing the code to receive it with.
+    SendText {
+        /// The message to send. Read from stdin if not given.
+        text: Option<String>,
+        /// The number of words in the code. Defaults to the one in the settings.
+        #[arg(short, long)]
+        length: Option<usize>,
+    },
+    /// Receives files and folders, or a message, from the peer that generated the given code.
+    Receive {
+        /// The Pylon code to receive with.
+        code: String,
+        /// The directory to save everything in. Defaults to the one in the settings.
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+        /// What to do with names that are already taken. Defaults to the one in the settings.
+        #[arg(long, value_enum)]
+        on_collision: Option<Collision>,
+        /// Accepts the offer without asking.
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+/// What to do when a received file or folder has the same name as an existing one.
+#[derive(Clone, Copy, ValueEnum)]
+enum Collision {
+    Rename,
+    Overwrite,
+    Skip,
+    /// Fail, listing the names that are taken.
+    Ask,
+}
+
+impl From<Collision> for CollisionPolicy {
+    fn from(collision: Collision) -> Self {
+        match collision {
+            Collision::Rename => CollisionPolicy::Rename,
+            Collision::Overwrite => CollisionPolicy::Overwrite,
+            Collision::Skip => CollisionPolicy::Skip,
+            Collision::Ask => CollisionPolicy::Ask,
+        }
+    }
+}
+
+/// What's shared with the desktop app, loaded from the directories it uses.
+struct Context {
+    settings: Settings,
+    history: HistoryStore,
+    resume: ResumeStore,
+}
+
+impl Context {
+    fn load() -> Result<Self, Error> {
+        // Tauri names the app's directories after its identifier.
+        let app_dir = |dir: Option<PathBuf>| {
+            dir.map(|dir| dir.join(APP_ID)).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "could not determine the app's directories",
+                )
+            })
+        };
+        let config_dir = app_dir(tauri::api::path::config_dir())?;
+        let data_dir = app_dir(tauri::api::path::data_dir())?;
+
+        Ok(Self {
+            settings: SettingsStore::load(config_dir.join(SETTINGS_FILE_NAME)).get(),
+            history: HistoryStore::new(data_dir.join(HISTORY_FILE_NAME)),
+            resume: ResumeStore::new(data_dir.join(RESUME_DIR_NAME)),
+        })
+    }
+
+    /// Builds a new Pylon, configured from the settings.
+    fn build_pylon(&self) -> Result<Pylon, Error> {
+        transfer::build_pylon(APP_ID.to_string(), &self.settings)
+    }
+
+    /// Records a finished transfer in the history, warning if it can't be.
+    fn record<T>(
+        &self,
+        record: TransferRecord,
+        result: &Result<T, Error>,
+        digest: Option<String>,
+        bytes_transferred: u64,
+    ) {
+        let entry = record.finish(result, digest, bytes_transferred);
+        if let Err(err) = self.history.record(&entry) {
+            eprintln!("warning: could not record the transfer: {err}");
+        }
+    }
+}
+
+/// Maps an error to an exit code, following the conventions of `sysexits.h`.
+fn exit_code(err: &Error) -> u8 {
+    match err {
+        Error::Pylon(_) => 69,
+        Error::Io(_) => 74,
+        Error::InvalidCodeLength { .. } | Error::InvalidLink { .. } => 64,
+        Error::InvalidServerUrl { .. } | Error::InvalidSettings(_) | Error::NoDownloadDir => 78,
+        Error::IntegrityMismatch { .. } | Error::TextTooLong { .. } => 65,
+        Error::InsufficientSpace { .. } | Error::NameCollision { .. } => 73,
+        Error::NoPendingCode | Error::NoPendingOffer | Error::UnknownSession(_) => 70,
+        Error::Rejected => 77,
+        Error::Cancelled => 130,
+    }
+}
+
+/// Describes an offer in a few words.
+fn describe(offer: &Offer) -> String {
+    format!("{} file(s), {}", offer.entry_count, HumanBytes(offer.size))
+}
+
+/// Creates a progress bar, hidden until it's drawn to.
+fn progress_bar() -> ProgressBar {
+    let bar = ProgressBar::hidden();
+    bar.set_style(
+        ProgressStyle::with_template(PROGRESS_TEMPLATE)
+            .unwrap_or_else(|_| ProgressStyle::default_bar()),
+    );
+
+    bar
+}
+
+/// Creates a progress handler that updates the given progress bar.
+fn progress_handler(bar: &ProgressBar) -> impl FnMut(u64, u64) + Send + 'static {
+    let bar = bar.clone();
+    move |done, total| {
+        bar.set_length(total);
+        bar.set_position(done);
+    }
+}
+
+/// Generates a Pylon code, unless cancelled first.
+async fn gen_code(
+    pylon: &mut Pylon,
+    length: usize,
+    cancel: &CancellationToken,
+) -> Result<String, Error> {
+    tokio::select! {
+        code = pylon.gen_code(length) => Ok(code?),
+        _ = cancel.cancelled() => Err(Error::Cancelled),
+    }
+}
+
+/// Asks whether to accept an offer, on the terminal.
+async fn confirm() -> Result<bool, Error> {
+    eprint!("Accept? [y/N] ");
+    io::stderr().flush()?;
+
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer).map(|_| answer)
+    })
+    .await
+    .map_err(|_| Error::Cancelled)??;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Reads stdin to its end, on a blocking thread.
+async fn read_stdin(limit: u64) -> Result<String, Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut input = String::new();
+        io::stdin()
+            .lock()
+            .take(limit)
+            .read_to_string(&mut input)
+            .map(|_| input)
+    })
+    .await
+    .map_err(io::Error::other)?
+    .map_err(Error::from)
+}
+
+/// Reads a message to send from stdin.
+async fn read_text() -> Result<String, Error> {
+    // Reading one byte more than allowed is enough to tell that it's too long.
+    read_stdin(MAX_MESSAGE_SIZE + 1).await
+}
+
+/// Sends files and folders with a Pylon whose code has been handed out.
+async fn send_outgoing(
+    ctx: &Context,
+    pylon: &mut Pylon,
+    outgoing: Outgoing,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let offer = outgoing.offer();
+    let bar = progress_bar();
+    bar.set_draw_target(ProgressDrawTarget::stderr());
+    let record = TransferRecord::start(Direction::Send, offer.names, offer.size);
+    let mut result = transfer::send(pylon, outgoing, progress_handler(&bar), cancel).await;
+    bar.finish_and_clear();
+
+    // Pylon may report a cancelled transfer as either a success or a failure.
+    if cancel.is_cancelled() {
+        result = Err(Error::Cancelled);
+    }
+
+    let digest = result.as_ref().ok().cloned();
+    ctx.record(record, &result, digest, bar.position());
+
+    eprintln!("Sent. SHA-256: {}", result?);
+
+    Ok(())
+}
+
+/// Sends a message with a Pylon whose code has been handed out.
+async fn send_message(
+    ctx: &Context,
+    pylon: &mut Pylon,
+    text: String,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let size = text.len() as u64;
+    let record = TransferRecord::start(Direction::Send, Vec::new(), size);
+    let mut result = transfer::send_text(pylon, text, |_, _| {}, cancel).await;
+    if cancel.is_cancelled() {
+        result = Err(Error::Cancelled);
+    }
+
+    let digest = result.as_ref().ok().cloned();
+    let bytes_sent = if result.is_ok() { size } else { 0 };
+    ctx.record(record, &result, digest, bytes_sent);
+
+    eprintln!("Sent. SHA-256: {}", result?);
+
+    Ok(())
+}
+
+async fn run_gen_code(
+    ctx: &Context,
+    length: Option<usize>,
+    text: bool,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let length = code::validate_length(length.unwrap_or(ctx.settings.code_length))?;
+    let mut pylon = ctx.build_pylon()?;
+    let code = gen_code(&mut pylon, length, cancel).await?;
+    eprintln!("On the other side, receive with:");
+    // The code alone goes to stdout, so that scripts can pick it up.
+    println!("{code}");
+    io::stdout().flush()?;
+
+    if text {
+        eprintln!("Reading the message to send from stdin...");
+        let text = read_text().await?;
+        return send_message(ctx, &mut pylon, text, cancel).await;
+    }
+
+    eprintln!("Reading the paths to send from stdin, one per line...");
+    let paths: Vec<PathBuf> = read_stdin(u64::MAX)
+        .await?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect();
+    let outgoing = Outgoing::new(&paths).await?;
+    eprintln!("Sending {}.", describe(&outgoing.offer()));
+    send_outgoing(ctx, &mut pylon, outgoing, cancel).await
+}
+
+async fn run_send(
+    ctx: &Context,
+    paths: Vec<PathBuf>,
+    length: Option<usize>,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let length = code::validate_length(length.unwrap_or(ctx.settings.code_length))?;
+    let outgoing = Outgoing::new(&paths).await?;
+
+    let mut pylon = ctx.build_pylon()?;
+    let code = gen_code(&mut pylon, length, cancel).await?;
+    eprintln!(
+        "Sending {}. On the other side, receive with:",
+        describe(&outgoing.offer())
+    );
+    // The code alone goes to stdout, so that scripts can pick it up.
+    println!("{code}");
+
+    send_outgoing(ctx, &mut pylon, outgoing, cancel).await
+}
+
+async fn run_send_text(
+    ctx: &Context,
+    text: Option<String>,
+    length: Option<usize>,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let length = code::validate_length(length.unwrap_or(ctx.settings.code_length))?;
+    let text = match text {
+        Some(text) => text,
+        None => read_text().await?,
+    };
+
+    let mut pylon = ctx.build_pylon()?;
+    let code = gen_code(&mut pylon, length, cancel).await?;
+    eprintln!("Sending a message. On the other side, receive with:");
+    println!("{code}");
+
+    send_message(ctx, &mut pylon, text, cancel).await
+}
+
+async fn run_receive(
+    ctx: &Context,
+    code: String,
+    dir: Option<PathBuf>,
+    on_collision: Option<Collision>,
+    yes: bool,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let destination_dir = match dir {
+        Some(dir) => dir,
+        None => ctx.settings.download_dir()?,
+    };
+    let policy = on_collision.map_or(ctx.settings.collision_policy, CollisionPolicy::from);
+
+    let pylon = ctx.build_pylon()?;
+    let bar = progress_bar();
+    let mut record = TransferRecord::start(Direction::Receive, Vec::new(), 0);
+    let connected = Incoming::connect(
+        pylon,
+        code,
+        &ctx.resume,
+        Box::new(progress_handler(&bar)),
+        cancel,
+    )
+    .await?;
+
+    let incoming = match connected {
+        Connected::Offer(incoming) => *incoming,
+        Connected::Message(message) => {
+            let size = message.text.len() as u64;
+            record.set_contents(Vec::new(), size);
+            ctx.record(record, &Ok::<_, Error>(()), Some(message.digest), size);
+
+            // Only the message goes to stdout, exactly as it was sent.
+            print!("{}", message.text);
+            io::stdout().flush()?;
+            return Ok(());
+        }
+    };
+
+    let offer = incoming.offer().clone();
+    eprintln!("Offered {}:", describe(&offer));
+    for name in &offer.names {
+        eprintln!("  {name}");
+    }
+    if offer.resumed_bytes > 0 {
+        eprintln!(
+            "Resuming an earlier transfer, {} already received.",
+            HumanBytes(offer.resumed_bytes)
+        );
+    }
+
+    record.set_contents(offer.names.clone(), offer.size);
+    if !yes && !confirm().await? {
+        let rejected = incoming.reject().await.and(Err::<(), _>(Error::Cancelled));
+        ctx.record(record, &rejected, None, 0);
+        return rejected;
+    }
+
+    // Let the peer know why nothing is coming, rather than leaving them waiting.
+    let plan = async {
+        tokio::fs::create_dir_all(&destination_dir).await?;
+        transfer::check_free_space(&destination_dir, offer.size).await?;
+        Plan::new(&destination_dir, &offer.names, policy, process::id().into()).await
+    }
+    .await;
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(err) => {
+            let _ = incoming.reject().await;
+            return Err(err);
+        }
+    };
+
+    bar.set_draw_target(ProgressDrawTarget::stderr());
+    let mut result = incoming.accept(plan, cancel).await;
+    bar.finish_and_clear();
+
+    if cancel.is_cancelled() {
+        result = Err(Error::Cancelled);
+    }
+
+    let digest = result
+        .as_ref()
+        .ok()
+        .and_then(|received| received.digest.clone());
+    ctx.record(record, &result, digest, bar.position());
+
+    let received = result?;
+    for path in &received.paths {
+        println!("{}", path.display());
+    }
+    match (received.digest, received.verified) {
+        (Some(digest), true) => eprintln!("Received and verified. SHA-256: {digest}"),
+        (Some(digest), false) => eprintln!("Received. SHA-256 (not verified): {digest}"),
+        (None, _) => eprintln!("Everything was skipped."),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Ctrl+C cancels the transfer, so that the peer is told.
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        }
+    });
+
+    let result = match Context::load() {
+        Ok(ctx) => match cli.command {
+            Command::GenCode { length, text } => run_gen_code(&ctx, length, text, &cancel).await,
+            Command::Send { paths, length } => run_send(&ctx, paths, length, &cancel).await,
+            Command::SendText { text, length } => run_send_text(&ctx, text, length, &cancel).await,
+            Command::Receive {
+                code,
+                dir,
+                on_collision,
+                yes,
+            } => run_receive(&ctx, code, dir, on_collision, yes, &cancel).await,
+        },
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(exit_code(&err))
+        }
+    }
+}
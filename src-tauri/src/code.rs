@@ -0,0 +1,139 @@
+use crate::error::Error;
+
+/// The shortest code we're willing to generate. Anything shorter is too easy to guess.
+pub const MIN_CODE_LENGTH: usize = 2;
+
+/// The longest code we're willing to generate. Anything longer is a pain to type.
+pub const MAX_CODE_LENGTH: usize = 8;
+
+/// The code length used when none is specified.
+pub const DEFAULT_CODE_LENGTH: usize = 2;
+
+/// Checks that the given code length is within range.
+///
+/// # Arguments
+///
+/// * `code_length` - The length of the code to generate.
+pub fn validate_length(code_length: usize) -> Result<usize, Error> {
+    if (MIN_CODE_LENGTH..=MAX_CODE_LENGTH).contains(&code_length) {
+        Ok(code_length)
+    } else {
+        Err(Error::InvalidCodeLength {
+            length: code_length,
+            min: MIN_CODE_LENGTH,
+            max: MAX_CODE_LENGTH,
+        })
+    }
+}
+
+/// The most digits a code's nameplate can have.
+const MAX_NAMEPLATE_DIGITS: usize = 6;
+
+/// The longest word a code can contain.
+const MAX_WORD_LENGTH: usize = 16;
+
+/// Indicates if the given code looks like one we could have generated, such as
+/// `7-guitarist-revenge`.
+///
+/// This only checks the shape of the code: a numeric nameplate, followed by a number of words
+/// within range.
+///
+/// # Arguments
+///
+/// * `code` - The code to check.
+pub fn is_well_formed(code: &str) -> bool {
+    let mut parts = code.split('-');
+    let nameplate = parts.next().unwrap_or_default();
+    if nameplate.is_empty()
+        || nameplate.len() > MAX_NAMEPLATE_DIGITS
+        || !nameplate.bytes().all(|b| b.is_ascii_digit())
+    {
+        return false;
+    }
+
+    let words: Vec<&str> = parts.collect();
+    (MIN_CODE_LENGTH..=MAX_CODE_LENGTH).contains(&words.len())
+        && words.iter().all(|word| {
+            !word.is_empty()
+                && word.len() <= MAX_WORD_LENGTH
+                && word.bytes().all(|b| b.is_ascii_lowercase())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pylon::PylonBuilder;
+    use crate::testing::rendezvous_server;
+
+    /// Generates a code of the given length, against a local rendezvous server.
+    async fn generate_code(rendezvous_url: &str, code_length: usize) -> String {
+        let mut pylon = PylonBuilder::default()
+            .id("com.pylon.test".to_string())
+            .rendezvous_url(rendezvous_url.to_string())
+            .build()
+            .unwrap();
+
+        pylon.gen_code(code_length).await.unwrap()
+    }
+
+    #[test]
+    fn validate_length_accepts_bounds() {
+        assert_eq!(validate_length(2).unwrap(), 2);
+        assert_eq!(validate_length(8).unwrap(), 8);
+        assert!(validate_length(DEFAULT_CODE_LENGTH).is_ok());
+    }
+
+    #[test]
+    fn validate_length_rejects_out_of_range() {
+        for length in [0, 1, 9, usize::MAX] {
+            assert!(matches!(
+                validate_length(length),
+                Err(Error::InvalidCodeLength { length: l, min: 2, max: 8 }) if l == length
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn codes_of_every_valid_length_are_well_formed() {
+        let rendezvous_url = rendezvous_server().await;
+
+        for length in MIN_CODE_LENGTH..=MAX_CODE_LENGTH {
+            let code = generate_code(&rendezvous_url, validate_length(length).unwrap()).await;
+            assert!(is_well_formed(&code), "{code}");
+            assert_eq!(code.split('-').count() - 1, length, "{code}");
+        }
+    }
+
+    #[tokio::test]
+    async fn codes_with_too_few_or_too_many_words_are_malformed() {
+        let rendezvous_url = rendezvous_server().await;
+
+        let code = generate_code(&rendezvous_url, MIN_CODE_LENGTH).await;
+        let (shorter, _) = code.rsplit_once('-').unwrap();
+        assert!(!is_well_formed(shorter), "{shorter}");
+
+        let code = generate_code(&rendezvous_url, MAX_CODE_LENGTH).await;
+        let longer = format!("{code}-tomato");
+        assert!(!is_well_formed(&longer), "{longer}");
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        for code in [
+            "",
+            "7",
+            "guitarist-revenge",
+            "x-guitarist-revenge",
+            "1234567-guitarist-revenge",
+            "7-Guitarist-revenge",
+            "7-guitarist--revenge",
+            "7-guitarist-revenge-",
+            "7-guitarist-révenge",
+            "7-guitarist revenge-tomato",
+            "7-guitaristguitarist-revenge",
+        ] {
+            assert!(!is_well_formed(code), "{code}");
+        }
+    }
+}
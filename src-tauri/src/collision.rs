@@ -0,0 +1,394 @@
+use crate::error::Error;
+use crate::settings::CollisionPolicy;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The extension of the hidden folders files and folders are received into.
+const PART_EXTENSION: &str = "pylon-part";
+
+/// Where a received top-level file or folder goes.
+struct Placement {
+    /// The name it was offered under.
+    name: String,
+    /// Where it ends up, or `None` if it's skipped.
+    target: Option<Target>,
+}
+
+/// Where a received top-level file or folder ends up.
+struct Target {
+    path: PathBuf,
+    /// Where it's received, before being moved to its final path.
+    part: PathBuf,
+    /// The hidden folder holding the part, which belongs to this transfer alone.
+    part_dir: PathBuf,
+}
+
+/// Decides where each received top-level file or folder goes, following a collision policy.
+///
+/// Everything is received into a hidden `.<name>.<id>.pylon-part` folder next to its target,
+/// and only moved into place once the whole transfer has completed. That way, a failed transfer
+/// never clobbers an existing file. The folders are created exclusively, so that nothing but
+/// what the transfer created itself is ever removed.
+pub struct Plan {
+    destination_dir: PathBuf,
+    placements: Vec<Placement>,
+}
+
+impl Plan {
+    /// Plans where to save the given files and folders.
+    ///
+    /// Fails with [`Error::NameCollision`] if the policy is to ask, and any of the names are
+    /// already taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination_dir` - The directory to save the files and folders in.
+    /// * `names` - The names of the offered top-level files and folders.
+    /// * `policy` - What to do with names that are already taken.
+    /// * `id` - Identifies the transfer, so that its part folders are told apart from those of
+    ///   other transfers.
+    pub async fn new(
+        destination_dir: &Path,
+        names: &[String],
+        policy: CollisionPolicy,
+        id: u64,
+    ) -> Result<Self, Error> {
+        let mut taken = HashSet::new();
+        let mut collisions = Vec::new();
+        let mut placements = Vec::with_capacity(names.len());
+
+        for name in names {
+            let mut target_name = name.clone();
+            if taken.contains(name) || exists(&destination_dir.join(name)).await? {
+                match policy {
+                    CollisionPolicy::Rename => {
+                        target_name = free_name(destination_dir, name, &taken).await?;
+                    }
+                    CollisionPolicy::Overwrite => {}
+                    CollisionPolicy::Skip => {
+                        placements.push((name.clone(), None));
+                        continue;
+                    }
+                    CollisionPolicy::Ask => collisions.push(name.clone()),
+                }
+            }
+
+            taken.insert(target_name.clone());
+            placements.push((name.clone(), Some(target_name)));
+        }
+
+        if !collisions.is_empty() {
+            return Err(Error::NameCollision { names: collisions });
+        }
+
+        // Only once it's clear the transfer goes ahead, so that nothing is left to clean up.
+        let mut plan = Self {
+            destination_dir: destination_dir.to_path_buf(),
+            placements: Vec::with_capacity(placements.len()),
+        };
+        for (name, target_name) in placements {
+            let target = match target_name {
+                Some(target_name) => match reserve_part(destination_dir, &target_name, id).await {
+                    Ok(part_dir) => Some(Target {
+                        path: destination_dir.join(&target_name),
+                        part: part_dir.join(&target_name),
+                        part_dir,
+                    }),
+                    Err(err) => {
+                        plan.discard().await;
+                        return Err(err.into());
+                    }
+                },
+                None => None,
+            };
+            plan.placements.push(Placement { name, target });
+        }
+
+        Ok(plan)
+    }
+
+    /// The directory everything is saved in.
+    pub fn destination_dir(&self) -> &Path {
+        &self.destination_dir
+    }
+
+    /// Indicates if every file and folder is skipped.
+    pub fn skips_all(&self) -> bool {
+        self.placements.iter().all(|p| p.target.is_none())
+    }
+
+    /// Returns where the given top-level file or folder should be received, or `None` if it's
+    /// skipped.
+    pub fn part_path(&self, name: &str) -> Option<&Path> {
+        self.placements
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.target.as_ref())
+            .map(|target| target.part.as_path())
+    }
+
+    /// Returns where each top-level file or folder should be received, keyed by name.
+    ///
+    /// Skipped files and folders map to `None`.
+    pub fn part_paths(&self) -> HashMap<String, Option<PathBuf>> {
+        self.placements
+            .iter()
+            .map(|p| (p.name.clone(), p.target.as_ref().map(|t| t.part.clone())))
+            .collect()
+    }
+
+    /// Moves everything that was received into place.
+    ///
+    /// Returns the paths of the saved files and folders.
+    pub async fn commit(self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+
+        for placement in self.placements {
+            let Some(target) = placement.target else {
+                continue;
+            };
+
+            // Renaming replaces files, but not folders.
+            if fs::symlink_metadata(&target.path)
+                .await
+                .is_ok_and(|metadata| metadata.is_dir())
+            {
+                fs::remove_dir_all(&target.path).await?;
+            }
+            fs::rename(&target.part, &target.path).await?;
+            fs::remove_dir(&target.part_dir).await?;
+
+            paths.push(target.path);
+        }
+
+        Ok(paths)
+    }
+
+    /// Removes anything that was partially received, along with the part folders.
+    pub async fn discard(self) {
+        for target in self.placements.into_iter().filter_map(|p| p.target) {
+            let _ = fs::remove_dir_all(&target.part_dir).await;
+        }
+    }
+}
+
+/// Creates the hidden folder the given file or folder is received into, and returns its path.
+///
+/// A folder left behind by a transfer that was cut short is never reused, but passed over for
+/// one with a number added, such as `.photo.jpg.7-1.pylon-part`.
+async fn reserve_part(destination_dir: &Path, name: &str, id: u64) -> io::Result<PathBuf> {
+    for n in 0.. {
+        let part_dir = match n {
+            0 => destination_dir.join(format!(".{name}.{id}.{PART_EXTENSION}")),
+            n => destination_dir.join(format!(".{name}.{id}-{n}.{PART_EXTENSION}")),
+        };
+        match fs::create_dir(&part_dir).await {
+            Ok(()) => return Ok(part_dir),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    unreachable!("there's always a free name")
+}
+
+/// Finds a variant of the given name that isn't taken yet, such as `photo (1).jpg`.
+async fn free_name(
+    destination_dir: &Path,
+    name: &str,
+    taken: &HashSet<String>,
+) -> io::Result<String> {
+    // Folders and files without an extension get the suffix at the very end.
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name, String::new()),
+    };
+
+    for n in 1.. {
+        let candidate = format!("{stem} ({n}){extension}");
+        if !taken.contains(&candidate) && !exists(&destination_dir.join(&candidate)).await? {
+            return Ok(candidate);
+        }
+    }
+
+    unreachable!("there's always a free name")
+}
+
+/// Indicates if anything exists at the given path, without following symlinks.
+async fn exists(path: &Path) -> io::Result<bool> {
+    match fs::symlink_metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Lists what's in the given directory, sorted.
+    fn list(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    /// Receives everything the plan doesn't skip, and returns the names it was saved under.
+    ///
+    /// Names with a dot in them are received as files, the others as folders with a file in them.
+    async fn receive(plan: Plan) -> Vec<String> {
+        for (name, part) in plan.part_paths() {
+            let Some(part) = part else {
+                continue;
+            };
+            if name.contains('.') {
+                std::fs::write(part, "received").unwrap();
+            } else {
+                std::fs::create_dir(&part).unwrap();
+                std::fs::write(part.join("new.txt"), "received").unwrap();
+            }
+        }
+
+        let destination_dir = plan.destination_dir().to_path_buf();
+        let paths = plan.commit().await.unwrap();
+        assert!(list(&destination_dir)
+            .iter()
+            .all(|name| !name.ends_with(PART_EXTENSION)));
+
+        paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn taken_names_are_renamed_to_the_first_free_one() {
+        let dir = TempDir::new().unwrap();
+        for name in ["a.txt", "a (1).txt", ".bashrc", "archive.tar.gz"] {
+            std::fs::write(dir.path().join(name), "existing").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("folder")).unwrap();
+
+        let offered = names(&["a.txt", "folder", ".bashrc", "archive.tar.gz", "new.txt"]);
+        let plan = Plan::new(dir.path(), &offered, CollisionPolicy::Rename, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            receive(plan).await,
+            [
+                "a (2).txt",
+                "folder (1)",
+                ".bashrc (1)",
+                "archive.tar (1).gz",
+                "new.txt"
+            ]
+        );
+        let existing = std::fs::read_to_string(dir.path().join("a.txt")).unwrap();
+        assert_eq!(existing, "existing");
+    }
+
+    #[tokio::test]
+    async fn taken_names_can_be_skipped() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "existing").unwrap();
+
+        let plan = Plan::new(dir.path(), &names(&["a.txt"]), CollisionPolicy::Skip, 1)
+            .await
+            .unwrap();
+        assert!(plan.skips_all());
+        assert_eq!(plan.part_path("a.txt"), None);
+
+        let offered = names(&["a.txt", "b.txt"]);
+        let plan = Plan::new(dir.path(), &offered, CollisionPolicy::Skip, 1)
+            .await
+            .unwrap();
+        assert!(!plan.skips_all());
+        assert_eq!(receive(plan).await, ["b.txt"]);
+
+        let existing = std::fs::read_to_string(dir.path().join("a.txt")).unwrap();
+        assert_eq!(existing, "existing");
+    }
+
+    #[tokio::test]
+    async fn asking_fails_with_the_taken_names() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "existing").unwrap();
+        std::fs::create_dir(dir.path().join("folder")).unwrap();
+
+        let offered = names(&["a.txt", "b.txt", "folder"]);
+        let planned = Plan::new(dir.path(), &offered, CollisionPolicy::Ask, 1).await;
+
+        let Err(Error::NameCollision { names }) = planned else {
+            panic!("expected a name collision");
+        };
+        assert_eq!(names, ["a.txt", "folder"]);
+        assert_eq!(list(dir.path()), ["a.txt", "folder"]);
+    }
+
+    #[tokio::test]
+    async fn overwriting_replaces_files_and_folders() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "existing").unwrap();
+        std::fs::create_dir(dir.path().join("folder")).unwrap();
+        std::fs::write(dir.path().join("folder/old.txt"), "existing").unwrap();
+
+        let offered = names(&["a.txt", "folder"]);
+        let plan = Plan::new(dir.path(), &offered, CollisionPolicy::Overwrite, 1)
+            .await
+            .unwrap();
+        assert_eq!(receive(plan).await, ["a.txt", "folder"]);
+
+        let received = std::fs::read_to_string(dir.path().join("a.txt")).unwrap();
+        assert_eq!(received, "received");
+        assert_eq!(list(&dir.path().join("folder")), ["new.txt"]);
+    }
+
+    #[tokio::test]
+    async fn only_the_part_folders_of_the_transfer_are_removed() {
+        let dir = TempDir::new().unwrap();
+        let names = ["a.txt".to_string()];
+        std::fs::create_dir(dir.path().join(".a.txt.1.pylon-part")).unwrap();
+        std::fs::write(dir.path().join("a.txt.part"), "mine").unwrap();
+
+        let plan = Plan::new(dir.path(), &names, CollisionPolicy::Ask, 1)
+            .await
+            .unwrap();
+        let part = plan.part_path("a.txt").unwrap().to_path_buf();
+        assert_eq!(part, dir.path().join(".a.txt.1-1.pylon-part/a.txt"));
+        std::fs::write(&part, "received").unwrap();
+        plan.discard().await;
+
+        assert_eq!(list(dir.path()), [".a.txt.1.pylon-part", "a.txt.part"]);
+    }
+
+    #[tokio::test]
+    async fn the_longest_names_can_still_be_renamed_and_received() {
+        let dir = TempDir::new().unwrap();
+        let name = crate::filename::sanitize(&format!("{}.txt", "a".repeat(300)));
+        std::fs::write(dir.path().join(&name), "taken").unwrap();
+
+        let names = [name.clone()];
+        let plan = Plan::new(dir.path(), &names, CollisionPolicy::Rename, u64::MAX)
+            .await
+            .unwrap();
+        std::fs::write(plan.part_path(&name).unwrap(), "received").unwrap();
+        let paths = plan.commit().await.unwrap();
+
+        let renamed = paths[0].file_name().unwrap().to_string_lossy();
+        assert!(renamed.ends_with(" (1).txt"));
+        assert_eq!(std::fs::read_to_string(&paths[0]).unwrap(), "received");
+    }
+}
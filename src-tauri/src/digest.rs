@@ -0,0 +1,132 @@
+use sha2::{Digest, Sha256};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The length of a hex-encoded SHA-256 digest.
+pub const DIGEST_LEN: usize = 64;
+
+/// Hex-encodes a finished hash.
+fn encode(hasher: Sha256) -> String {
+    format!("{:x}", hasher.finalize())
+}
+
+/// Returns the hex-encoded SHA-256 digest of the given data.
+pub fn digest(data: &[u8]) -> String {
+    encode(Sha256::new_with_prefix(data))
+}
+
+/// Computes the SHA-256 digest of everything written through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns the inner writer, along with the hex-encoded digest of everything written so far.
+    pub fn finish(self) -> (W, String) {
+        (self.inner, encode(self.hasher))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.hasher.update(&buf[..written]);
+        }
+
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Gives access to the digest computed by a [`HashingReader`], once it's done.
+#[derive(Clone, Debug)]
+pub struct DigestHandle(Arc<OnceLock<String>>);
+
+impl DigestHandle {
+    /// Returns the hex-encoded digest, or `None` if not enough has been read yet.
+    pub fn get(&self) -> Option<&str> {
+        self.0.get().map(String::as_str)
+    }
+}
+
+/// Computes the SHA-256 digest of the first bytes read through it.
+///
+/// Only a prefix is hashed, so that a digest can be sent right after the data it covers.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+    remaining: u64,
+    digest: DigestHandle,
+}
+
+impl<R> HashingReader<R> {
+    /// Wraps the given reader, hashing its first `len` bytes.
+    ///
+    /// Returns the reader, along with a handle to get the digest from.
+    pub fn new(inner: R, len: u64) -> (Self, DigestHandle) {
+        let digest = DigestHandle(Arc::default());
+        let mut reader = Self {
+            inner,
+            hasher: Some(Sha256::new()),
+            remaining: len,
+            digest: digest.clone(),
+        };
+        reader.finish_if_done();
+
+        (reader, digest)
+    }
+
+    fn finish_if_done(&mut self) {
+        if self.remaining == 0 {
+            if let Some(hasher) = self.hasher.take() {
+                let _ = self.digest.0.set(encode(hasher));
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            let read = &buf.filled()[filled..];
+            let hashed = self.remaining.min(read.len() as u64) as usize;
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&read[..hashed]);
+            }
+            self.remaining -= hashed as u64;
+            self.finish_if_done();
+        }
+
+        poll
+    }
+}
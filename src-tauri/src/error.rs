@@ -0,0 +1,128 @@
+use crate::pylon::PylonError;
+use crate::session::SessionId;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// Errors that can be returned by our commands.
+///
+/// Errors are serialized as `{ code, message, details }`, where `code` is a stable identifier
+/// that the frontend can use to look up a localized message, `message` is an English fallback,
+/// and `details` holds any values the localized message may need.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Pylon(#[from] PylonError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("code length must be between {min} and {max}, got {length}")]
+    InvalidCodeLength {
+        length: usize,
+        min: usize,
+        max: usize,
+    },
+
+    #[error("invalid server URL {url}: {reason}")]
+    InvalidServerUrl { url: String, reason: String },
+
+    #[error("invalid link: {reason}")]
+    InvalidLink { reason: String },
+
+    #[error("invalid settings: {0}")]
+    InvalidSettings(String),
+
+    #[error("the received data is corrupted: expected digest {expected}, got {actual}")]
+    IntegrityMismatch { expected: String, actual: String },
+
+    #[error("not enough free space: {required} bytes required, {available} bytes available")]
+    InsufficientSpace { required: u64, available: u64 },
+
+    #[error("the message is too long: {size} bytes, at most {max} bytes allowed")]
+    TextTooLong { size: u64, max: u64 },
+
+    #[error("already exists: {}", names.join(", "))]
+    NameCollision { names: Vec<String> },
+
+    #[error("could not determine the downloads directory")]
+    NoDownloadDir,
+
+    #[error("no Pylon code has been generated")]
+    NoPendingCode,
+
+    #[error("no offer has been received")]
+    NoPendingOffer,
+
+    #[error("unknown transfer session: {0}")]
+    UnknownSession(SessionId),
+
+    #[error("the offer was rejected")]
+    Rejected,
+
+    #[error("the transfer was cancelled")]
+    Cancelled,
+}
+
+impl Error {
+    /// A stable, machine-readable identifier for this kind of error.
+    ///
+    /// These are used as translation keys by the frontend, so never change an existing one.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Pylon(_) => "pylon",
+            Self::Io(_) => "io",
+            Self::InvalidCodeLength { .. } => "invalid_code_length",
+            Self::InvalidServerUrl { .. } => "invalid_server_url",
+            Self::InvalidLink { .. } => "invalid_link",
+            Self::InvalidSettings(_) => "invalid_settings",
+            Self::IntegrityMismatch { .. } => "integrity_mismatch",
+            Self::InsufficientSpace { .. } => "insufficient_space",
+            Self::TextTooLong { .. } => "text_too_long",
+            Self::NameCollision { .. } => "name_collision",
+            Self::NoDownloadDir => "no_download_dir",
+            Self::NoPendingCode => "no_pending_code",
+            Self::NoPendingOffer => "no_pending_offer",
+            Self::UnknownSession(_) => "unknown_session",
+            Self::Rejected => "rejected",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// Values that give more context about the error, if any.
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            Self::Io(err) => Some(json!({ "kind": err.kind().to_string() })),
+            Self::InvalidCodeLength { length, min, max } => {
+                Some(json!({ "length": length, "min": min, "max": max }))
+            }
+            Self::InvalidServerUrl { url, reason } => Some(json!({ "url": url, "reason": reason })),
+            Self::InvalidLink { reason } => Some(json!({ "reason": reason })),
+            Self::InvalidSettings(reason) => Some(json!({ "reason": reason })),
+            Self::IntegrityMismatch { expected, actual } => {
+                Some(json!({ "expected": expected, "actual": actual }))
+            }
+            Self::InsufficientSpace {
+                required,
+                available,
+            } => Some(json!({ "required": required, "available": available })),
+            Self::TextTooLong { size, max } => Some(json!({ "size": size, "max": max })),
+            Self::NameCollision { names } => Some(json!({ "names": names })),
+            Self::UnknownSession(id) => Some(json!({ "id": id })),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Error", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
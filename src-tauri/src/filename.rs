@@ -0,0 +1,158 @@
+/// The characters that are not allowed in file names, on at least one platform.
+const FORBIDDEN_CHARS: &[char] = &['/', '\\', '<', '>', ':', '"', '|', '?', '*'];
+
+/// Names that Windows reserves for devices, regardless of their extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The longest file name, in bytes, that most file systems allow.
+const FS_MAX_NAME_LEN: usize = 255;
+
+/// The room left for what a name may still be extended with once received, such as the ` (1)`
+/// that sets it apart from a taken name, or the `.<name>.<id>.pylon-part` of the folder it's
+/// received into.
+const HEADROOM: usize = 56;
+
+/// The longest a sanitized name is, in bytes.
+const MAX_NAME_LEN: usize = FS_MAX_NAME_LEN - HEADROOM;
+
+/// The longest extension, in bytes and including its dot, that's kept when a name is truncated.
+const MAX_EXTENSION_LEN: usize = 16;
+
+/// What a file name is replaced with, if nothing usable is left of it.
+const FALLBACK_NAME: &str = "unnamed";
+
+/// Turns a file name sent by the peer into one that is safe to create on any platform.
+///
+/// The result is always a single, plain path component: separators, control characters and
+/// characters that Windows forbids are replaced, `.` and `..` are replaced altogether, and
+/// reserved Windows device names are prefixed. Long names are truncated, keeping their
+/// extension, so that there's still room to extend them.
+///
+/// # Arguments
+///
+/// * `name` - The file name to sanitize.
+pub fn sanitize(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || FORBIDDEN_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(0, '_');
+    }
+
+    if sanitized.len() > MAX_NAME_LEN {
+        let extension = match sanitized.rfind('.') {
+            Some(dot) if dot > 0 && sanitized.len() - dot <= MAX_EXTENSION_LEN => {
+                sanitized.split_off(dot)
+            }
+            _ => String::new(),
+        };
+
+        let mut end = MAX_NAME_LEN - extension.len();
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+        sanitized.push_str(&extension);
+    }
+
+    // Windows silently drops trailing dots and spaces, which would turn "..." into "..".
+    sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+
+    if sanitized.is_empty() {
+        return FALLBACK_NAME.to_string();
+    }
+
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_plain_names() {
+        assert_eq!(sanitize("report.pdf"), "report.pdf");
+        assert_eq!(sanitize(".bashrc"), ".bashrc");
+        assert_eq!(sanitize("photo 2024 (1).jpg"), "photo 2024 (1).jpg");
+        assert_eq!(sanitize("résumé.txt"), "résumé.txt");
+    }
+
+    #[test]
+    fn replaces_dot_names() {
+        assert_eq!(sanitize("."), FALLBACK_NAME);
+        assert_eq!(sanitize(".."), FALLBACK_NAME);
+        assert_eq!(sanitize("..."), FALLBACK_NAME);
+        assert_eq!(sanitize(""), FALLBACK_NAME);
+        assert_eq!(sanitize("name. . "), "name");
+    }
+
+    #[test]
+    fn replaces_separators() {
+        assert_eq!(sanitize("a/b"), "a_b");
+        assert_eq!(sanitize("a\\b"), "a_b");
+        assert_eq!(sanitize("/etc/passwd"), "_etc_passwd");
+        assert_eq!(sanitize("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(sanitize("C:\\x"), "C__x");
+    }
+
+    #[test]
+    fn replaces_forbidden_and_control_characters() {
+        assert_eq!(sanitize("a<b>c:d\"e|f?g*h"), "a_b_c_d_e_f_g_h");
+        assert_eq!(sanitize("a\nb\0c\x7fd\te"), "a_b_c_d_e");
+    }
+
+    #[test]
+    fn prefixes_reserved_names() {
+        assert_eq!(sanitize("CON.txt"), "_CON.txt");
+        assert_eq!(sanitize("com1"), "_com1");
+        assert_eq!(sanitize("lpt9.tar.gz"), "_lpt9.tar.gz");
+        assert_eq!(sanitize("nul .txt"), "_nul .txt");
+        assert_eq!(sanitize("CONSOLE.txt"), "CONSOLE.txt");
+        assert_eq!(sanitize("com10"), "com10");
+    }
+
+    #[test]
+    fn truncates_long_names() {
+        assert_eq!(sanitize(&"a".repeat(300)), "a".repeat(MAX_NAME_LEN));
+
+        // Multi-byte characters are never split.
+        let sanitized = sanitize(&"é".repeat(200));
+        assert_eq!(sanitized, "é".repeat(MAX_NAME_LEN / 2));
+    }
+
+    #[test]
+    fn keeps_the_extension_of_long_names() {
+        let name = format!("{}.tar.gz", "a".repeat(300));
+        let expected = format!("{}.gz", "a".repeat(MAX_NAME_LEN - 3));
+        assert_eq!(sanitize(&name), expected);
+
+        // Anything too long to be an extension is truncated along with the rest.
+        let name = format!("a.{}", "b".repeat(300));
+        assert_eq!(sanitize(&name).len(), MAX_NAME_LEN);
+        assert!(sanitize(&name).starts_with("a.bbb"));
+    }
+
+    #[test]
+    fn sanitized_names_are_stable() {
+        let long = format!("{}.txt", "é".repeat(200));
+        for name in ["..", "a/b", "CON.txt", "...", &"é".repeat(200), &long] {
+            let sanitized = sanitize(name);
+            assert_eq!(sanitize(&sanitized), sanitized);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::error::Error;
+use crate::progress::Direction;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The name of the history file, within the app's data directory.
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// How a transfer ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Outcome {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// A finished transfer, as recorded in the history.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub direction: Direction,
+    /// The names of the transferred top-level files and folders.
+    pub names: Vec<String>,
+    /// The total size of the transferred files, in bytes.
+    pub size: u64,
+    pub bytes_transferred: u64,
+    /// When the transfer started, in milliseconds since the Unix epoch.
+    pub started_at: u64,
+    /// When the transfer finished, in milliseconds since the Unix epoch.
+    pub finished_at: u64,
+    /// How long the transfer took, in milliseconds.
+    pub duration: u64,
+    pub outcome: Outcome,
+    /// The hex-encoded SHA-256 digest of what was transferred, if it completed.
+    pub digest: Option<String>,
+    /// The code of the error the transfer failed with, if it did.
+    pub error_code: Option<String>,
+}
+
+/// A transfer that's underway, to be recorded in the history once it finishes.
+pub struct TransferRecord {
+    direction: Direction,
+    names: Vec<String>,
+    size: u64,
+    started_at: SystemTime,
+}
+
+impl TransferRecord {
+    /// Starts recording a transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the transfer.
+    /// * `names` - The names of the top-level files and folders being transferred.
+    /// * `size` - The total size of the files being transferred.
+    pub fn start(direction: Direction, names: Vec<String>, size: u64) -> Self {
+        Self {
+            direction,
+            names,
+            size,
+            started_at: SystemTime::now(),
+        }
+    }
+
+    /// Records what's being transferred, for transfers that only find out once they've started.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names of the top-level files and folders being transferred.
+    /// * `size` - The total size of the files being transferred.
+    pub fn set_contents(&mut self, names: Vec<String>, size: u64) {
+        self.names = names;
+        self.size = size;
+    }
+
+    /// Finishes recording the transfer, with the given outcome.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - The result of the transfer.
+    /// * `digest` - The digest of what was transferred, if anything.
+    /// * `bytes_transferred` - The number of bytes transferred.
+    pub fn finish<T>(
+        self,
+        result: &Result<T, Error>,
+        digest: Option<String>,
+        bytes_transferred: u64,
+    ) -> HistoryEntry {
+        let finished_at = SystemTime::now();
+        let (outcome, error_code) = match result {
+            Ok(_) => (Outcome::Completed, None),
+            Err(Error::Cancelled) => (Outcome::Cancelled, None),
+            Err(err) => (Outcome::Failed, Some(err.code().to_string())),
+        };
+
+        HistoryEntry {
+            direction: self.direction,
+            names: self.names,
+            size: self.size,
+            bytes_transferred,
+            started_at: unix_millis(self.started_at),
+            finished_at: unix_millis(finished_at),
+            duration: finished_at
+                .duration_since(self.started_at)
+                .map_or(0, |d| d.as_millis() as u64),
+            outcome,
+            digest,
+            error_code,
+        }
+    }
+}
+
+/// Keeps a record of every finished transfer, in an append-only JSON lines file.
+pub struct HistoryStore {
+    path: PathBuf,
+    /// Keeps entries from being appended while the history is being cleared, and vice versa.
+    lock: Mutex<()>,
+}
+
+impl HistoryStore {
+    /// Creates a store that keeps the history in the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the history file.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends an entry to the history.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry to append.
+    pub fn record(&self, entry: &HistoryEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let _lock = self.lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+
+        // A line cut short by a crash would otherwise run into this one, and both would be lost.
+        if file.seek(SeekFrom::End(0))? > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last != *b"\n" {
+                line.insert(0, b'\n');
+            }
+        }
+
+        // A single write, so that a crash can only ever cut the last line short.
+        file.write_all(&line)
+    }
+
+    /// Returns every entry in the history, most recent first.
+    ///
+    /// Lines that can't be read, such as one cut short by a crash, are skipped.
+    pub fn list(&self) -> io::Result<Vec<HistoryEntry>> {
+        let _lock = self.lock.lock().unwrap();
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+        entries.reverse();
+
+        Ok(entries)
+    }
+
+    /// Removes every entry from the history.
+    pub fn clear(&self) -> io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes every entry in the history to the given file, as a JSON array, most recent first.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to export to.
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        let entries = self.list()?;
+        fs::write(path, serde_json::to_vec_pretty(&entries)?)
+    }
+}
+
+/// Converts a point in time to milliseconds since the Unix epoch.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(names: &[&str]) -> HistoryEntry {
+        let names = names.iter().map(|name| name.to_string()).collect();
+        TransferRecord::start(Direction::Send, names, 5).finish(&Ok::<_, Error>(()), None, 5)
+    }
+
+    fn names(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| entry.names[0].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn entries_are_listed_most_recent_first() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path().join("data").join(HISTORY_FILE_NAME));
+        assert!(store.list().unwrap().is_empty());
+
+        for name in ["a", "b", "c"] {
+            store.record(&entry(&[name])).unwrap();
+        }
+
+        assert_eq!(names(&store.list().unwrap()), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn a_line_cut_short_is_skipped_without_losing_the_next_one() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(HISTORY_FILE_NAME);
+        let store = HistoryStore::new(path.clone());
+        store.record(&entry(&["a"])).unwrap();
+
+        let line = serde_json::to_string(&entry(&["b"])).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&line.as_bytes()[..line.len() / 2]).unwrap();
+        drop(file);
+
+        store.record(&entry(&["c"])).unwrap();
+        assert_eq!(names(&store.list().unwrap()), ["c", "a"]);
+    }
+
+    #[test]
+    fn clearing_removes_every_entry() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path().join(HISTORY_FILE_NAME));
+        store.clear().unwrap();
+
+        store.record(&entry(&["a"])).unwrap();
+        store.clear().unwrap();
+        assert!(store.list().unwrap().is_empty());
+
+        store.record(&entry(&["b"])).unwrap();
+        assert_eq!(names(&store.list().unwrap()), ["b"]);
+    }
+
+    #[test]
+    fn exports_every_entry_most_recent_first() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path().join(HISTORY_FILE_NAME));
+        store.record(&entry(&["a"])).unwrap();
+        store.record(&entry(&["b", "c"])).unwrap();
+
+        let path = dir.path().join("export.json");
+        store.export(&path).unwrap();
+
+        let exported: Vec<HistoryEntry> =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(names(&exported), ["b", "a"]);
+        assert_eq!(exported[0].names, ["b", "c"]);
+        assert_eq!(exported[0].outcome, Outcome::Completed);
+    }
+}
@@ -0,0 +1,40 @@
+//! The backend shared by the desktop app and the command-line client.
+
+pub mod archive;
+pub mod code;
+pub mod collision;
+pub mod deep_link;
+pub mod digest;
+pub mod error;
+pub mod filename;
+pub mod history;
+pub mod logging;
+pub mod progress;
+pub mod pylon;
+pub mod qr;
+pub mod relay;
+pub mod resume;
+pub mod session;
+pub mod settings;
+pub mod single_instance;
+#[cfg(test)]
+mod testing;
+pub mod transfer;
+
+/// The ID Pylons are identified with. Peers must use the same ID to connect.
+///
+/// Keep in sync with `tauri.bundle.identifier` in `tauri.conf.json`, which the app's config and
+/// data directories are also named after.
+pub const APP_ID: &str = "com.pylon-apps.pylon-desktop";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_id_matches_the_bundle_identifier() {
+        let config: serde_json::Value =
+            serde_json::from_str(include_str!("../tauri.conf.json")).unwrap();
+        assert_eq!(config["tauri"]["bundle"]["identifier"], APP_ID);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::settings::LogLevel;
+use std::io;
+use std::path::Path;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+/// The prefix of the log files' names, which are suffixed with their date.
+const LOG_FILE_PREFIX: &str = "pylon";
+
+/// The extension of the log files.
+const LOG_FILE_SUFFIX: &str = "log";
+
+/// The number of log files kept, one per day, before the oldest is removed.
+const MAX_LOG_FILES: usize = 7;
+
+/// The crate that carries out transfers, whose logs are dropped whatever the level. They include
+/// Pylon codes and the messages exchanged with the peer.
+const TRANSFER_TARGET: &str = "magic_wormhole";
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Writes logs to rotating files, and lets their level be changed on the fly.
+///
+/// Pylon codes grant access to a transfer, so they must never be logged, and neither must the
+/// contents of transferred files. File names, sizes and session IDs are fine. That's why the logs
+/// of the transfer crate itself, bridged from the `log` crate, are always left out.
+pub struct Logging {
+    level: reload::Handle<LevelFilter, Registry>,
+    /// Flushes the logs once dropped, so it's kept for as long as the app runs.
+    _guard: WorkerGuard,
+}
+
+impl Logging {
+    /// Starts writing logs to the given directory, as the global default subscriber.
+    ///
+    /// In debug builds, logs are written to the console as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `log_dir` - The directory to write the log files in.
+    /// * `level` - The most detailed level to log.
+    pub fn init(log_dir: &Path, level: LogLevel) -> io::Result<Self> {
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_FILE_PREFIX)
+            .filename_suffix(LOG_FILE_SUFFIX)
+            .max_log_files(MAX_LOG_FILES)
+            .build(log_dir)
+            .map_err(io::Error::other)?;
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        let (filter, handle) = reload::Layer::new(LevelFilter::from(level));
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(
+                Targets::new()
+                    .with_default(LevelFilter::TRACE)
+                    .with_target(TRANSFER_TARGET, LevelFilter::OFF),
+            )
+            .with(fmt::layer().with_writer(writer).with_ansi(false))
+            .with(cfg!(debug_assertions).then(fmt::layer))
+            .try_init()
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            level: handle,
+            _guard: guard,
+        })
+    }
+
+    /// Changes the most detailed level to log.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The most detailed level to log.
+    pub fn set_level(&self, level: LogLevel) {
+        let _ = self.level.reload(LevelFilter::from(level));
+    }
+}
@@ -1,13 +1,152 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use libpylon::{Pylon, PylonBuilder, PylonError};
-use tokio::sync::Mutex;
+use pylon_desktop::collision::Plan;
+use pylon_desktop::deep_link::{LinkInbox, ReceiveLink, LINK_EVENT};
+use pylon_desktop::error::Error;
+use pylon_desktop::history::{HistoryEntry, HistoryStore, TransferRecord, HISTORY_FILE_NAME};
+use pylon_desktop::logging::Logging;
+use pylon_desktop::progress::{Direction, ProgressTracker, PROGRESS_EVENT};
+use pylon_desktop::pylon::Pylon;
+use pylon_desktop::relay::RelayStatus;
+use pylon_desktop::resume::{ResumeStore, RESUME_DIR_NAME};
+use pylon_desktop::session::{SessionId, SessionInfo, SessionManager, SessionState};
+use pylon_desktop::settings::{CollisionPolicy, Settings, SettingsStore, SETTINGS_FILE_NAME};
+use pylon_desktop::single_instance::Instance;
+use pylon_desktop::transfer::{Connected, Incoming, Message, Offer, Outgoing};
+use pylon_desktop::{code, deep_link, qr, relay, transfer, APP_ID};
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use tauri::Manager;
 
-#[derive(Default)]
-struct State {
-    code: Mutex<Option<String>>,
-    pylon: Mutex<Option<Pylon>>,
+/// An offer from a peer, along with the session it belongs to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PendingOffer {
+    id: SessionId,
+    #[serde(flatten)]
+    offer: Offer,
+}
+
+/// A message from a peer, along with the session it belongs to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceivedMessage {
+    id: SessionId,
+    #[serde(flatten)]
+    message: Message,
+}
+
+/// What a peer sent, once connected.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum Connection {
+    Offer(PendingOffer),
+    Message(ReceivedMessage),
+}
+
+/// The result of a completed send.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SendResult {
+    paths: Vec<PathBuf>,
+    bytes_sent: u64,
+    /// The hex-encoded SHA-256 digest of what was sent.
+    digest: String,
+}
+
+/// The result of a completed receive.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceiveResult {
+    paths: Vec<PathBuf>,
+    bytes_received: u64,
+    /// The hex-encoded SHA-256 digest of what was received. `None` if everything was skipped.
+    digest: Option<String>,
+    /// Whether the digest matched the sender's. Only Pylon peers send one.
+    verified: bool,
+}
+
+/// Builds a new Pylon, identified by our app ID and configured from the settings.
+fn build_pylon(app: &tauri::AppHandle) -> Result<Pylon, Error> {
+    let settings = app.state::<SettingsStore>().get();
+
+    transfer::build_pylon(APP_ID.to_string(), &settings)
+}
+
+/// Creates a progress handler for the given session.
+///
+/// The handler records the number of bytes transferred in the session, and emits progress events
+/// to all windows.
+fn progress_handler(
+    app: tauri::AppHandle,
+    id: SessionId,
+    direction: Direction,
+) -> impl FnMut(u64, u64) + Send + 'static {
+    let mut tracker = ProgressTracker::new(id, direction);
+
+    move |done, total| {
+        app.state::<SessionManager>()
+            .set_bytes_transferred(id, done);
+        if let Some(progress) = tracker.update(done, total) {
+            let _ = app.emit_all(PROGRESS_EVENT, progress);
+        }
+    }
+}
+
+/// Returns the number of bytes transferred in the given session.
+fn bytes_transferred(sessions: &SessionManager, id: SessionId) -> u64 {
+    sessions.get(id).map_or(0, |info| info.bytes_transferred)
+}
+
+/// Records a finished transfer in the history.
+///
+/// The history is only a convenience, so failing to write to it doesn't fail the transfer.
+fn record_history<T>(
+    history: &HistoryStore,
+    sessions: &SessionManager,
+    id: SessionId,
+    record: TransferRecord,
+    result: &Result<T, Error>,
+    digest: Option<String>,
+) {
+    let entry = record.finish(result, digest, bytes_transferred(sessions, id));
+    if let Err(err) = history.record(&entry) {
+        tracing::warn!(session = id, "could not record the transfer: {err}");
+    }
+}
+
+/// Handles a `pylon://` link opened with the app, leaving it for the window to take.
+///
+/// Invalid links are only logged, without their contents, since they may hold a code.
+fn open_link(app: &tauri::AppHandle, link: &str) {
+    match deep_link::parse(link) {
+        Ok(link) => {
+            tracing::info!("opened a receive link");
+            app.state::<LinkInbox>().deliver(link);
+            let _ = app.emit_all(LINK_EVENT, ());
+        }
+        Err(err) => tracing::warn!(code = err.code(), details = ?err.details(), "ignored a link"),
+    }
+}
+
+/// Handles the command-line arguments of a launch, whether ours or a later one's.
+///
+/// Only `pylon://` links are understood; anything else is ignored.
+fn handle_args(app: &tauri::AppHandle, args: &[String]) {
+    if let Some(link) = deep_link::find_link(args) {
+        open_link(app, &link);
+    }
+}
+
+/// Brings the main window to the front.
+fn focus_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
 }
 
 /// Indicates if we're currently running in "release" mode.
@@ -16,39 +155,533 @@ fn is_release_mode() -> bool {
     !cfg!(debug_assertions)
 }
 
-/// Generates a Pylon code.
+/// Creates a new send session, for `gen_code` to generate its code.
+///
+/// The session exists before its code does, so that it can be cancelled while it's generated.
+#[tauri::command]
+fn create_send_session(sessions: tauri::State<'_, SessionManager>) -> SessionId {
+    sessions.create(Direction::Send, SessionState::Generating)
+}
+
+/// Generates the Pylon code of the given send session.
 ///
 /// # Arguments
 ///
-/// * `code_length` - The length of the code to generate.
+/// * `id` - The ID of the session returned by `create_send_session`.
+/// * `code_length` - The length of the code to generate. Defaults to the one in the settings.
 #[tauri::command]
 async fn gen_code(
-    code_length: usize,
+    id: SessionId,
+    code_length: Option<usize>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionManager>,
+    settings: tauri::State<'_, SettingsStore>,
+) -> Result<String, Error> {
+    let result = async {
+        // The session may have been cancelled before it got this far.
+        if sessions.cancel_token(id)?.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        let code_length = code::validate_length(code_length.unwrap_or(settings.get().code_length))?;
+        let pylon = build_pylon(&app);
+        let (code, pylon) = sessions
+            .run(id, async move {
+                let mut pylon = pylon?;
+                let code = pylon.gen_code(code_length).await?;
+
+                Ok((code, pylon))
+            })
+            .await?;
+        sessions.set_code(id, code.clone(), pylon)?;
+
+        Ok(code)
+    }
+    .await;
+
+    if result.is_err() {
+        sessions.finish(id, &result);
+    }
+
+    result
+}
+
+/// Renders the code of the given session as a QR code, in SVG.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session returned by `create_send_session`.
+/// * `as_uri` - Whether to wrap the code in a `pylon://receive` link, which opens the app.
+#[tauri::command]
+fn code_qr(
+    id: SessionId,
+    as_uri: bool,
+    sessions: tauri::State<'_, SessionManager>,
+) -> Result<String, Error> {
+    let code = sessions.get(id)?.code.ok_or(Error::NoPendingCode)?;
+    let data = if as_uri {
+        deep_link::receive_uri(&code)
+    } else {
+        code
+    };
+
+    Ok(qr::render_svg(&data)?)
+}
+
+/// Sends files and folders using the Pylon of the given session.
+///
+/// Waits for the peer to connect with the session's code before streaming everything to them in
+/// a single transfer.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session returned by `create_send_session`.
+/// * `paths` - The paths of the files and folders to send.
+#[tauri::command]
+async fn send_file(
+    id: SessionId,
+    paths: Vec<PathBuf>,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionManager>,
+    history: tauri::State<'_, HistoryStore>,
+) -> Result<SendResult, Error> {
+    let outgoing = Outgoing::new(&paths).await?;
+    let mut pylon = sessions.take_pylon(id, paths.clone())?;
+    let cancel = sessions.cancel_token(id)?;
+
+    let offer = outgoing.offer();
+    let record = TransferRecord::start(Direction::Send, offer.names, offer.size);
+
+    let handler = progress_handler(app, id, Direction::Send);
+    let result = sessions
+        .run(id, async move {
+            let result = transfer::send(&mut pylon, outgoing, handler, &cancel).await;
+
+            // Pylon may report a cancelled transfer as either a success or a failure.
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            result
+        })
+        .await
+        .map(|digest| SendResult {
+            paths,
+            bytes_sent: bytes_transferred(&sessions, id),
+            digest,
+        });
+
+    sessions.finish(id, &result);
+    let digest = result.as_ref().ok().map(|sent| sent.digest.clone());
+    record_history(&history, &sessions, id, record, &result, digest);
+
+    result
+}
+
+/// Sends a short message using the Pylon of the given session.
+///
+/// Waits for the peer to connect with the session's code, like `send_file`. The peer is shown
+/// the message, which is never saved on either side.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session returned by `create_send_session`.
+/// * `text` - The message to send.
+#[tauri::command]
+async fn send_text(
+    id: SessionId,
+    text: String,
     app: tauri::AppHandle,
-    state: tauri::State<'_, State>,
-) -> Result<String, PylonError> {
-    let config = app.config();
-    let mut pylon = PylonBuilder::default()
-        .id(config.tauri.bundle.identifier.clone())
-        .build()?;
-    let code = pylon.gen_code(code_length).await?;
+    sessions: tauri::State<'_, SessionManager>,
+    history: tauri::State<'_, HistoryStore>,
+) -> Result<SendResult, Error> {
+    let mut pylon = sessions.take_pylon(id, Vec::new())?;
+    let cancel = sessions.cancel_token(id)?;
+
+    // Messages have no name, and their contents stay out of the history.
+    let record = TransferRecord::start(Direction::Send, Vec::new(), text.len() as u64);
 
-    let mut state_code = state.code.lock().await;
-    let mut state_pylon = state.pylon.lock().await;
+    let handler = progress_handler(app, id, Direction::Send);
+    let result = sessions
+        .run(id, async move {
+            let result = transfer::send_text(&mut pylon, text, handler, &cancel).await;
 
-    state_code.replace(code.clone());
-    state_pylon.replace(pylon);
+            // Pylon may report a cancelled transfer as either a success or a failure.
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
 
-    Ok(code)
+            result
+        })
+        .await
+        .map(|digest| SendResult {
+            paths: Vec::new(),
+            bytes_sent: bytes_transferred(&sessions, id),
+            digest,
+        });
+
+    sessions.finish(id, &result);
+    let digest = result.as_ref().ok().map(|sent| sent.digest.clone());
+    record_history(&history, &sessions, id, record, &result, digest);
+
+    result
+}
+
+/// Connects to the peer that generated the given Pylon code, and waits for their offer.
+///
+/// Nothing is saved until the offer is accepted with `accept_offer`. If the offer is for an
+/// archive that was partially received before, accepting it resumes the earlier transfer.
+///
+/// Messages aren't offered, but received right away, which finishes the session.
+///
+/// # Arguments
+///
+/// * `code` - The Pylon code to connect with.
+#[tauri::command]
+async fn connect_receive(
+    code: String,
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, SessionManager>,
+    resume: tauri::State<'_, ResumeStore>,
+    history: tauri::State<'_, HistoryStore>,
+) -> Result<Connection, Error> {
+    let id = sessions.create(Direction::Receive, SessionState::Waiting);
+    let cancel = sessions.cancel_token(id)?;
+    let mut record = TransferRecord::start(Direction::Receive, Vec::new(), 0);
+
+    let pylon = build_pylon(&app);
+    let resume = resume.inner().clone();
+    let handler = Box::new(progress_handler(app, id, Direction::Receive));
+    let result = sessions
+        .run(id, async move {
+            Incoming::connect(pylon?, code, &resume, handler, &cancel).await
+        })
+        .await
+        .and_then(|connected| match connected {
+            Connected::Offer(incoming) => {
+                let offer = incoming.offer().clone();
+                sessions.set_incoming(id, *incoming)?;
+
+                Ok(Connection::Offer(PendingOffer { id, offer }))
+            }
+            Connected::Message(message) => Ok(Connection::Message(ReceivedMessage { id, message })),
+        });
+
+    match &result {
+        Ok(Connection::Offer(_)) => {}
+        Ok(Connection::Message(received)) => {
+            sessions.finish(id, &result);
+            record.set_contents(Vec::new(), received.message.text.len() as u64);
+            let digest = Some(received.message.digest.clone());
+            record_history(&history, &sessions, id, record, &result, digest);
+        }
+        Err(_) => sessions.finish(id, &result),
+    }
+
+    result
+}
+
+/// Accepts the offer of the given session, saving everything in the destination directory.
+///
+/// The offer is left pending if the destination doesn't have enough free space, so that
+/// another one can be picked.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session returned by `connect_receive`.
+/// * `destination_dir` - The directory to save the received files and folders in. Defaults to
+///   the download directory from the settings.
+/// * `collision_policy` - What to do with names that are already taken. Defaults to the policy
+///   from the settings. If that's to ask, the offer is left pending.
+#[tauri::command]
+async fn accept_offer(
+    id: SessionId,
+    destination_dir: Option<PathBuf>,
+    collision_policy: Option<CollisionPolicy>,
+    sessions: tauri::State<'_, SessionManager>,
+    settings: tauri::State<'_, SettingsStore>,
+    history: tauri::State<'_, HistoryStore>,
+) -> Result<ReceiveResult, Error> {
+    let settings = settings.get();
+    let destination_dir = match destination_dir {
+        Some(dir) => dir,
+        None => settings.download_dir()?,
+    };
+    tokio::fs::create_dir_all(&destination_dir).await?;
+
+    let offer = sessions.offer(id)?;
+    transfer::check_free_space(&destination_dir, offer.size).await?;
+    let plan = Plan::new(
+        &destination_dir,
+        &offer.names,
+        collision_policy.unwrap_or(settings.collision_policy),
+        id,
+    )
+    .await?;
+
+    let incoming = sessions.take_incoming(id)?;
+    let cancel = sessions.cancel_token(id)?;
+    let record = TransferRecord::start(Direction::Receive, offer.names, offer.size);
+
+    let result = sessions
+        .run(id, async move {
+            let result = incoming.accept(plan, &cancel).await;
+
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            result
+        })
+        .await
+        .map(|received| ReceiveResult {
+            paths: received.paths,
+            bytes_received: bytes_transferred(&sessions, id),
+            digest: received.digest,
+            verified: received.verified,
+        });
+
+    sessions.finish(id, &result);
+    let digest = result
+        .as_ref()
+        .ok()
+        .and_then(|received| received.digest.clone());
+    record_history(&history, &sessions, id, record, &result, digest);
+
+    result
+}
+
+/// Rejects the offer of the given session, letting the peer know.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session returned by `connect_receive`.
+#[tauri::command]
+async fn reject_offer(
+    id: SessionId,
+    sessions: tauri::State<'_, SessionManager>,
+    history: tauri::State<'_, HistoryStore>,
+) -> Result<(), Error> {
+    let incoming = sessions.take_incoming(id)?;
+    let offer = incoming.offer().clone();
+    let record = TransferRecord::start(Direction::Receive, offer.names, offer.size);
+    let result = incoming.reject().await;
+
+    // As far as the session and the history are concerned, a rejected offer is a cancelled one.
+    let cancelled = Err::<(), _>(Error::Cancelled);
+    sessions.finish(id, &cancelled);
+    record_history(&history, &sessions, id, record, &cancelled, None);
+
+    result
+}
+
+/// Cancels the given session.
+///
+/// Commands awaiting the cancelled session resolve with a cancellation error.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session to cancel.
+#[tauri::command]
+fn cancel_transfer(id: SessionId, sessions: tauri::State<'_, SessionManager>) -> Result<(), Error> {
+    sessions.cancel(id)
+}
+
+/// Lists all transfer sessions.
+#[tauri::command]
+fn list_sessions(sessions: tauri::State<'_, SessionManager>) -> Vec<SessionInfo> {
+    sessions.list()
+}
+
+/// Describes the given transfer session.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the session to describe.
+#[tauri::command]
+fn get_session(
+    id: SessionId,
+    sessions: tauri::State<'_, SessionManager>,
+) -> Result<SessionInfo, Error> {
+    sessions.get(id)
+}
+
+/// Returns the current settings.
+#[tauri::command]
+fn get_settings(settings: tauri::State<'_, SettingsStore>) -> Settings {
+    settings.get()
+}
+
+/// Updates some of the settings, and persists them.
+///
+/// Returns the updated settings.
+///
+/// # Arguments
+///
+/// * `changes` - The settings to change, as a partial settings object.
+#[tauri::command]
+fn update_settings(
+    changes: serde_json::Map<String, serde_json::Value>,
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, SettingsStore>,
+) -> Result<Settings, Error> {
+    let updated = settings.update(changes)?;
+    if let Some(logging) = app.try_state::<Logging>() {
+        logging.set_level(updated.log_level);
+    }
+
+    Ok(updated)
+}
+
+/// Checks whether each of the relays that would be used can be reached.
+///
+/// Returns the status of each relay, in order of preference.
+#[tauri::command]
+async fn test_relays(settings: tauri::State<'_, SettingsStore>) -> Result<Vec<RelayStatus>, Error> {
+    Ok(relay::check_all(relay::relay_urls(&settings.get())).await)
+}
+
+/// Lists every finished transfer, most recent first.
+#[tauri::command]
+fn list_history(history: tauri::State<'_, HistoryStore>) -> Result<Vec<HistoryEntry>, Error> {
+    Ok(history.list()?)
+}
+
+/// Removes every transfer from the history.
+#[tauri::command]
+fn clear_history(history: tauri::State<'_, HistoryStore>) -> Result<(), Error> {
+    Ok(history.clear()?)
+}
+
+/// Exports the history to a file, as a JSON array.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to export to.
+#[tauri::command]
+fn export_history(path: PathBuf, history: tauri::State<'_, HistoryStore>) -> Result<(), Error> {
+    Ok(history.export(&path)?)
+}
+
+/// Takes the `pylon://receive` link the app was last opened with, if it hasn't been taken yet.
+#[tauri::command]
+fn take_pending_link(links: tauri::State<'_, LinkInbox>) -> Option<ReceiveLink> {
+    links.take()
+}
+
+/// Opens the folder the log files are written in, with the OS's file manager.
+#[tauri::command]
+fn open_log_dir(app: tauri::AppHandle) -> Result<(), Error> {
+    let log_dir = app.path_resolver().app_log_dir().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not determine the app's log directory",
+        )
+    })?;
+    std::fs::create_dir_all(&log_dir)?;
+
+    Ok(open::that_detached(log_dir)?)
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Later launches hand their arguments over to the running instance, instead of opening a
+    // window of their own. If we can't tell whether one is running, run anyway.
+    let data_dir = tauri::api::path::data_dir().map(|dir| dir.join(APP_ID));
+    let primary = match data_dir.as_deref().map(Instance::acquire) {
+        Some(Ok(Instance::Primary(primary))) => Some(primary),
+        Some(Ok(Instance::Secondary(secondary))) => {
+            if let Err(err) = tauri::async_runtime::block_on(secondary.forward(&args)) {
+                eprintln!("could not reach the running instance: {err}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Err(err)) => {
+            eprintln!("could not check for a running instance: {err}");
+            None
+        }
+        None => None,
+    };
+
     tauri::Builder::default()
-        .manage(State {
-            code: Mutex::default(),
-            pylon: Mutex::default(),
+        .setup(move |app| {
+            let config_dir = app
+                .path_resolver()
+                .app_config_dir()
+                .ok_or("could not determine the app's config directory")?;
+            let settings = SettingsStore::load(config_dir.join(SETTINGS_FILE_NAME));
+
+            // Logs are only there to help, so failing to write them must not keep the app from
+            // starting.
+            let log_dir = app
+                .path_resolver()
+                .app_log_dir()
+                .ok_or("could not determine the app's log directory")?;
+            match Logging::init(&log_dir, settings.get().log_level) {
+                Ok(logging) => {
+                    app.manage(logging);
+                }
+                Err(err) => eprintln!("could not start logging: {err}"),
+            }
+            tracing::info!(version = %app.package_info().version, "starting");
+            app.manage(settings);
+
+            let data_dir = app
+                .path_resolver()
+                .app_data_dir()
+                .ok_or("could not determine the app's data directory")?;
+            app.manage(ResumeStore::new(data_dir.join(RESUME_DIR_NAME)));
+            app.manage(HistoryStore::new(data_dir.join(HISTORY_FILE_NAME)));
+
+            if let Some(primary) = primary {
+                let handle = app.handle();
+                tauri::async_runtime::spawn(async move {
+                    let result = primary
+                        .serve(move |args| {
+                            tracing::info!("forwarded a later launch");
+                            handle_args(&handle, &args);
+                            focus_main_window(&handle);
+                        })
+                        .await;
+                    if let Err(err) = result {
+                        tracing::warn!("stopped listening for later launches: {err}");
+                    }
+                });
+            }
+
+            // Where the `pylon://` scheme is registered, by the .msi and .deb bundles, opening a
+            // link launches us with it as an argument. macOS delivers links as Apple events
+            // instead, which Tauri 1 doesn't surface, so the scheme isn't registered there.
+            handle_args(&app.handle(), &args);
+
+            Ok(())
         })
-        .invoke_handler(tauri::generate_handler![is_release_mode, gen_code])
+        .manage(SessionManager::default())
+        .manage(LinkInbox::default())
+        .invoke_handler(tauri::generate_handler![
+            is_release_mode,
+            create_send_session,
+            gen_code,
+            code_qr,
+            send_file,
+            send_text,
+            connect_receive,
+            accept_offer,
+            reject_offer,
+            cancel_transfer,
+            list_sessions,
+            get_session,
+            get_settings,
+            update_settings,
+            test_relays,
+            list_history,
+            clear_history,
+            export_history,
+            take_pending_link,
+            open_log_dir
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,97 @@
+use crate::session::SessionId;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// The event that transfer progress is emitted on.
+pub const PROGRESS_EVENT: &str = "transfer://progress";
+
+/// The minimum interval between two progress updates.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The weight given to the most recent throughput sample when smoothing.
+const SMOOTHING_FACTOR: f64 = 0.3;
+
+/// The direction of a transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+/// A snapshot of a transfer's progress.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    pub id: SessionId,
+    pub direction: Direction,
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+    /// Smoothed throughput, in bytes per second.
+    pub throughput: f64,
+    /// Estimated time remaining, in seconds. `None` until the throughput is known.
+    pub eta: Option<f64>,
+}
+
+/// Turns raw progress callbacks into rate-limited [`Progress`] snapshots.
+pub struct ProgressTracker {
+    id: SessionId,
+    direction: Direction,
+    last_update: Option<(Instant, u64)>,
+    throughput: f64,
+}
+
+impl ProgressTracker {
+    /// Creates a new tracker for the given session.
+    pub fn new(id: SessionId, direction: Direction) -> Self {
+        Self {
+            id,
+            direction,
+            last_update: None,
+            throughput: 0.0,
+        }
+    }
+
+    /// Records the current progress of the transfer.
+    ///
+    /// Returns a snapshot if enough time has passed since the previous one, or if the transfer
+    /// has just completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_done` - The number of bytes transferred so far.
+    /// * `total_bytes` - The total number of bytes to transfer.
+    pub fn update(&mut self, bytes_done: u64, total_bytes: u64) -> Option<Progress> {
+        let now = Instant::now();
+        let finished = bytes_done >= total_bytes;
+
+        match self.last_update {
+            Some((at, _)) if !finished && now.duration_since(at) < UPDATE_INTERVAL => return None,
+            Some((at, bytes)) => {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                if elapsed > 0.0 {
+                    let sample = bytes_done.saturating_sub(bytes) as f64 / elapsed;
+                    self.throughput = if self.throughput == 0.0 {
+                        sample
+                    } else {
+                        SMOOTHING_FACTOR * sample + (1.0 - SMOOTHING_FACTOR) * self.throughput
+                    };
+                }
+            }
+            None => {}
+        }
+        self.last_update = Some((now, bytes_done));
+
+        let eta = (self.throughput > 0.0)
+            .then(|| total_bytes.saturating_sub(bytes_done) as f64 / self.throughput);
+
+        Some(Progress {
+            id: self.id,
+            direction: self.direction,
+            bytes_done,
+            total_bytes,
+            throughput: self.throughput,
+            eta,
+        })
+    }
+}
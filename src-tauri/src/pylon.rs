@@ -0,0 +1,307 @@
+use futures::io::{AsyncRead, AsyncWrite};
+use magic_wormhole::rendezvous::DEFAULT_RENDEZVOUS_SERVER;
+use magic_wormhole::transfer::{self, AppVersion, TransferError};
+use magic_wormhole::transit::{Abilities, RelayHint, RelayHintParseError};
+use magic_wormhole::{AppConfig, AppID, Code, Wormhole, WormholeError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use url::Url;
+
+/// Performs the handshake with the peer once they've connected, yielding the wormhole.
+type Handshake = Pin<Box<dyn Future<Output = Result<Wormhole, WormholeError>> + Send + Sync>>;
+
+/// Errors a Pylon may run into.
+#[derive(Debug, thiserror::Error)]
+pub enum PylonError {
+    #[error("the Pylon has no ID")]
+    MissingId,
+
+    #[error("a code has already been generated with this Pylon")]
+    CodeAlreadyGenerated,
+
+    #[error("no code has been generated with this Pylon")]
+    NoCode,
+
+    #[error("the peer sent an invalid message: {0}")]
+    InvalidMessage(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    InvalidUrl(#[from] url::ParseError),
+
+    #[error(transparent)]
+    InvalidRelay(#[from] RelayHintParseError),
+
+    // Boxed, as they're much larger than everything else.
+    #[error(transparent)]
+    Wormhole(Box<WormholeError>),
+
+    #[error(transparent)]
+    Transfer(Box<TransferError>),
+}
+
+impl From<WormholeError> for PylonError {
+    fn from(err: WormholeError) -> Self {
+        Self::Wormhole(Box::new(err))
+    }
+}
+
+impl From<TransferError> for PylonError {
+    fn from(err: TransferError) -> Self {
+        Self::Transfer(Box::new(err))
+    }
+}
+
+/// Builds a [`Pylon`].
+#[derive(Clone, Debug, Default)]
+pub struct PylonBuilder {
+    id: Option<String>,
+    rendezvous_url: Option<String>,
+    relay_urls: Vec<String>,
+    force_relay: bool,
+}
+
+impl PylonBuilder {
+    /// Sets the ID to identify the Pylon with. Peers must use the same ID to connect.
+    pub fn id(&mut self, id: String) -> &mut Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Sets the rendezvous server to connect to, instead of the public one.
+    pub fn rendezvous_url(&mut self, url: String) -> &mut Self {
+        self.rendezvous_url = Some(url);
+        self
+    }
+
+    /// Sets the transit relays to offer the peer, in order of preference. Without any, the peers
+    /// must be able to reach each other directly.
+    pub fn relay_urls(&mut self, urls: Vec<String>) -> &mut Self {
+        self.relay_urls = urls;
+        self
+    }
+
+    /// Only connects to the peer through a relay, never directly.
+    #[cfg(test)]
+    pub fn force_relay(&mut self) -> &mut Self {
+        self.force_relay = true;
+        self
+    }
+
+    /// Builds the Pylon. Nothing is connected to until it's used.
+    pub fn build(&self) -> Result<Pylon, PylonError> {
+        let id = self.id.clone().ok_or(PylonError::MissingId)?;
+        let rendezvous_url = self
+            .rendezvous_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_RENDEZVOUS_SERVER.to_string());
+        let relay_hints = self
+            .relay_urls
+            .iter()
+            .map(|url| Ok(RelayHint::from_urls(None, [Url::parse(url)?])?))
+            .collect::<Result<_, PylonError>>()?;
+
+        Ok(Pylon {
+            config: AppConfig {
+                id: AppID::new(id),
+                rendezvous_url: Cow::Owned(rendezvous_url),
+                app_version: AppVersion::default(),
+            },
+            relay_hints,
+            abilities: if self.force_relay {
+                Abilities::FORCE_RELAY
+            } else {
+                Abilities::ALL_ABILITIES
+            },
+            handshake: None,
+            wormhole: None,
+        })
+    }
+}
+
+/// One end of a transfer, over magic-wormhole.
+///
+/// A sending Pylon generates a code with `gen_code`, then waits for the peer to connect with it in
+/// `send_file`. A receiving Pylon connects with that code in `connect`, then waits for the file in
+/// `request_file`. Before the file is offered, the peers may exchange messages of their own.
+pub struct Pylon {
+    config: AppConfig<AppVersion>,
+    relay_hints: Vec<RelayHint>,
+    abilities: Abilities,
+    handshake: Option<Handshake>,
+    wormhole: Option<Wormhole>,
+}
+
+impl Pylon {
+    /// Generates a code, and keeps a mailbox open on the rendezvous server for the peer to connect
+    /// to with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `code_length` - The number of words in the code.
+    pub async fn gen_code(&mut self, code_length: usize) -> Result<String, PylonError> {
+        if self.handshake.is_some() || self.wormhole.is_some() {
+            return Err(PylonError::CodeAlreadyGenerated);
+        }
+
+        let (welcome, handshake) =
+            Wormhole::connect_without_code(self.config.clone(), code_length).await?;
+        self.handshake = Some(Box::pin(handshake));
+
+        Ok(welcome.code.0)
+    }
+
+    /// Connects to the peer that generated the given code.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code to connect with.
+    pub async fn connect(&mut self, code: String) -> Result<(), PylonError> {
+        if self.handshake.is_some() || self.wormhole.is_some() {
+            return Err(PylonError::CodeAlreadyGenerated);
+        }
+
+        let (_, wormhole) = Wormhole::connect_with_code(self.config.clone(), Code(code)).await?;
+        self.wormhole = Some(wormhole);
+
+        Ok(())
+    }
+
+    /// Sends a message to the peer, waiting for them to connect first if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to send, which is serialized as JSON.
+    pub async fn send_message<T: Serialize>(&mut self, message: &T) -> Result<(), PylonError> {
+        self.wormhole().await?.send_json(message).await?;
+
+        Ok(())
+    }
+
+    /// Waits for a message from the peer, waiting for them to connect first if needed.
+    pub async fn receive_message<T: DeserializeOwned>(&mut self) -> Result<T, PylonError> {
+        Ok(self.wormhole().await?.receive_json().await??)
+    }
+
+    /// Returns the wormhole to the peer, waiting for them to connect if they haven't yet.
+    async fn wormhole(&mut self) -> Result<&mut Wormhole, PylonError> {
+        if let Some(handshake) = self.handshake.take() {
+            self.wormhole = Some(handshake.await?);
+        }
+
+        self.wormhole.as_mut().ok_or(PylonError::NoCode)
+    }
+
+    /// Waits for the peer to connect with the generated code, then sends them a file.
+    ///
+    /// The peer sees the file's name and size, and only receives it once they accept it.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The file's contents, which must be exactly `size` bytes long.
+    /// * `name` - The name to offer the file under.
+    /// * `size` - The size of the file.
+    /// * `progress_handler` - Called with the number of bytes sent so far, and the total.
+    /// * `cancel` - Cancels the transfer once it resolves.
+    pub async fn send_file<R, P, C>(
+        &mut self,
+        reader: &mut R,
+        name: String,
+        size: u64,
+        progress_handler: P,
+        cancel: C,
+    ) -> Result<(), PylonError>
+    where
+        R: AsyncRead + Unpin,
+        P: FnMut(u64, u64) + 'static,
+        C: Future<Output = ()>,
+    {
+        self.wormhole().await?;
+        let wormhole = self.wormhole.take().ok_or(PylonError::NoCode)?;
+
+        transfer::send_file(
+            wormhole,
+            self.relay_hints.clone(),
+            reader,
+            name,
+            size,
+            self.abilities,
+            |_, _| {},
+            progress_handler,
+            cancel,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Waits for the peer's offer, once connected with `connect`.
+    ///
+    /// Returns `None` if cancelled first.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - Cancels waiting once it resolves.
+    pub async fn request_file<C>(&mut self, cancel: C) -> Result<Option<ReceiveRequest>, PylonError>
+    where
+        C: Future<Output = ()>,
+    {
+        let wormhole = self.wormhole.take().ok_or(PylonError::NoCode)?;
+        let request =
+            transfer::request_file(wormhole, self.relay_hints.clone(), self.abilities, cancel)
+                .await?;
+
+        Ok(request.map(|request| ReceiveRequest(Box::new(request))))
+    }
+}
+
+/// A file offered by the peer, waiting to be accepted or rejected.
+#[must_use]
+pub struct ReceiveRequest(Box<transfer::ReceiveRequest>);
+
+impl ReceiveRequest {
+    /// The name the file was offered under. This comes straight from the peer, so it must be
+    /// sanitized before being used as a path.
+    pub fn file_name(&self) -> Cow<'_, str> {
+        self.0.filename.to_string_lossy()
+    }
+
+    /// The size of the file, as announced by the peer.
+    pub fn file_size(&self) -> u64 {
+        self.0.filesize
+    }
+
+    /// Accepts the file, and receives it into the given writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where to write the file's contents.
+    /// * `progress_handler` - Called with the number of bytes received so far, and the total.
+    /// * `cancel` - Cancels the transfer once it resolves.
+    pub async fn accept<W, P, C>(
+        self,
+        writer: &mut W,
+        progress_handler: P,
+        cancel: C,
+    ) -> Result<(), PylonError>
+    where
+        W: AsyncWrite + Unpin,
+        P: FnMut(u64, u64) + 'static,
+        C: Future<Output = ()>,
+    {
+        self.0
+            .accept(|_, _| {}, progress_handler, writer, cancel)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Rejects the file, letting the peer know.
+    pub async fn reject(self) -> Result<(), PylonError> {
+        self.0.reject().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+use std::io;
+
+/// The minimum width and height of a rendered QR code, in pixels.
+const MIN_SIZE: u32 = 200;
+
+/// Renders the given data as a QR code, in SVG.
+///
+/// Everything is rendered locally, so the data never leaves the machine.
+///
+/// # Arguments
+///
+/// * `data` - The data to encode.
+pub fn render_svg(data: &str) -> io::Result<String> {
+    // Codes are short, so favor being scannable from a distance or a glossy screen.
+    let code = QrCode::with_error_correction_level(data, EcLevel::Q)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(MIN_SIZE, MIN_SIZE)
+        .quiet_zone(true)
+        .build())
+}
@@ -0,0 +1,84 @@
+use crate::error::Error;
+use crate::settings::Settings;
+use futures::future;
+use serde::Serialize;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use url::Url;
+
+/// The public transit relay, used unless disabled in the settings.
+pub const DEFAULT_RELAY_URL: &str = "tcp://transit.magic-wormhole.io:4001";
+
+/// How long to wait for a relay to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether a relay could be reached.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayStatus {
+    pub url: String,
+    pub reachable: bool,
+    /// How long it took to connect, in milliseconds.
+    pub latency: Option<u64>,
+    /// Why the relay couldn't be reached.
+    pub error: Option<String>,
+}
+
+/// Returns the relays to use, in order of preference.
+///
+/// The public relay comes last, so that it is only used when none of the configured relays can
+/// be reached.
+pub fn relay_urls(settings: &Settings) -> Vec<String> {
+    let mut urls = settings.relay_urls.clone();
+    if settings.use_default_relay && !urls.iter().any(|url| url == DEFAULT_RELAY_URL) {
+        urls.push(DEFAULT_RELAY_URL.to_string());
+    }
+
+    urls
+}
+
+/// Checks whether each of the given relays can be reached, all at once.
+///
+/// # Arguments
+///
+/// * `urls` - The URLs of the relays to check.
+pub async fn check_all(urls: Vec<String>) -> Vec<RelayStatus> {
+    future::join_all(urls.into_iter().map(check)).await
+}
+
+/// Checks whether the given relay can be reached, by opening a TCP connection to it.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the relay to check.
+pub async fn check(url: String) -> RelayStatus {
+    let started = Instant::now();
+    let result = connect(&url).await;
+
+    RelayStatus {
+        reachable: result.is_ok(),
+        latency: result.is_ok().then(|| started.elapsed().as_millis() as u64),
+        error: result.err().map(|err| err.to_string()),
+        url,
+    }
+}
+
+async fn connect(url: &str) -> Result<(), Error> {
+    let invalid = |reason: &str| Error::InvalidServerUrl {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let parsed = Url::parse(url).map_err(|err| invalid(&err.to_string()))?;
+    let host = parsed.host_str().ok_or_else(|| invalid("missing host"))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| invalid("missing port"))?;
+
+    tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use crate::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The name of the directory partial transfers are kept in, within the app's data directory.
+pub const RESUME_DIR_NAME: &str = "partial";
+
+/// The number of bytes at the start of an offer that are hashed into its fingerprint.
+pub const HASH_PREFIX_LEN: u64 = 1024 * 1024;
+
+/// How long a partial transfer is kept, waiting to be resumed.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The size of the chunks received bytes are spooled in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Identifies an offer, so that it can be recognized when it's offered again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fingerprint {
+    /// The name the offer is made under.
+    pub name: String,
+    pub size: u64,
+    /// The hex-encoded SHA-256 digest of the first [`HASH_PREFIX_LEN`] bytes of the offer.
+    pub hash_prefix: String,
+}
+
+/// Keeps the raw bytes of offers being received, so that an interrupted transfer can pick up
+/// where it left off.
+///
+/// Each offer is kept as a `.part` file, along with a sidecar holding its fingerprint. Both are
+/// removed once the offer has been received in full, turned down, or found to be corrupt. Those
+/// that are never resumed are removed after a week.
+#[derive(Clone)]
+pub struct ResumeStore {
+    dir: PathBuf,
+    /// The offers currently being received, which can't be received a second time meanwhile.
+    in_use: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ResumeStore {
+    /// Creates a store that keeps partial transfers in the given directory.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            in_use: Arc::default(),
+        }
+    }
+
+    /// Opens the spool for the given offer.
+    ///
+    /// If part of the same offer was received before, the spool picks up where it left off.
+    /// Otherwise, it starts out empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `fingerprint` - The fingerprint of the offer.
+    pub async fn open(&self, fingerprint: &Fingerprint) -> io::Result<Spool> {
+        let key = digest::digest(&serde_json::to_vec(fingerprint)?);
+        if !self.in_use.lock().unwrap().insert(key.clone()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "this offer is already being received",
+            ));
+        }
+
+        let mut spool = Spool {
+            store: self.clone(),
+            key,
+            offset: 0,
+        };
+
+        fs::create_dir_all(&self.dir).await?;
+        self.remove_stale().await;
+
+        let sidecar = fs::read(spool.sidecar_path()).await.ok();
+        let matches = sidecar
+            .and_then(|data| serde_json::from_slice::<Fingerprint>(&data).ok())
+            .is_some_and(|sidecar| sidecar == *fingerprint);
+        let len = fs::metadata(spool.part_path()).await.map_or(0, |m| m.len());
+
+        if matches && len <= fingerprint.size {
+            spool.offset = len;
+        } else {
+            fs::File::create(spool.part_path()).await?;
+        }
+
+        // Rewriting the sidecar also marks the partial transfer as recently used.
+        fs::write(spool.sidecar_path(), serde_json::to_vec(fingerprint)?).await?;
+
+        Ok(spool)
+    }
+
+    /// Removes the partial transfers that have been waiting to be resumed for too long.
+    async fn remove_stale(&self) {
+        let Ok(mut dir) = fs::read_dir(&self.dir).await else {
+            return;
+        };
+
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            let modified = entry.metadata().await.and_then(|m| m.modified());
+            let age = modified.map(|modified| SystemTime::now().duration_since(modified));
+            if let Ok(Ok(age)) = age {
+                if age > MAX_AGE {
+                    let _ = fs::remove_file(path.with_extension("part")).await;
+                    let _ = fs::remove_file(&path).await;
+                }
+            }
+        }
+    }
+}
+
+/// The raw bytes of an offer being received.
+pub struct Spool {
+    store: ResumeStore,
+    key: String,
+    offset: u64,
+}
+
+impl Spool {
+    fn part_path(&self) -> PathBuf {
+        self.store.dir.join(format!("{}.part", self.key))
+    }
+
+    fn sidecar_path(&self) -> PathBuf {
+        self.store.dir.join(format!("{}.json", self.key))
+    }
+
+    /// The number of bytes received in an earlier transfer.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Opens the bytes received in an earlier transfer, for reading.
+    pub async fn read_earlier(&self) -> io::Result<impl AsyncRead + Unpin> {
+        Ok(fs::File::open(self.part_path()).await?.take(self.offset))
+    }
+
+    /// Opens the spool for the rest of the bytes to be appended to.
+    pub async fn append(&self) -> io::Result<fs::File> {
+        fs::OpenOptions::new()
+            .append(true)
+            .open(self.part_path())
+            .await
+    }
+
+    /// Forgets about the offer, which can no longer be resumed.
+    pub async fn remove(self) {
+        let _ = fs::remove_file(self.part_path()).await;
+        let _ = fs::remove_file(self.sidecar_path()).await;
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        self.store.in_use.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Copies everything from the reader to the writer, appending it to the spool first.
+///
+/// That way, the spool always holds at least as much as the writer has been given.
+///
+/// # Arguments
+///
+/// * `reader` - Where the received bytes come from.
+/// * `spool` - The spool to append them to, opened with [`Spool::append`].
+/// * `writer` - Where the received bytes go next.
+pub async fn spool<R, W>(mut reader: R, spool: &mut fs::File, mut writer: W) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let copied = async {
+        loop {
+            let len = reader.read(&mut chunk).await?;
+            if len == 0 {
+                return Ok(());
+            }
+
+            spool.write_all(&chunk[..len]).await?;
+            writer.write_all(&chunk[..len]).await?;
+        }
+    }
+    .await;
+
+    // Whatever was received must make it to disk, even if passing it on failed.
+    spool.flush().await?;
+
+    copied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn fingerprint(hash_prefix: &str) -> Fingerprint {
+        Fingerprint {
+            name: "folder.pylon.tar".to_string(),
+            size: 1024,
+            hash_prefix: hash_prefix.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn the_same_offer_picks_up_where_it_left_off() {
+        let dir = TempDir::new().unwrap();
+        let store = ResumeStore::new(dir.path().to_path_buf());
+
+        let spool = store.open(&fingerprint("a")).await.unwrap();
+        assert_eq!(spool.offset(), 0);
+        let mut appended = spool.append().await.unwrap();
+        appended.write_all(&[1; 100]).await.unwrap();
+        appended.flush().await.unwrap();
+        drop(spool);
+
+        let spool = store.open(&fingerprint("a")).await.unwrap();
+        assert_eq!(spool.offset(), 100);
+        let mut earlier = Vec::new();
+        spool
+            .read_earlier()
+            .await
+            .unwrap()
+            .read_to_end(&mut earlier)
+            .await
+            .unwrap();
+        assert_eq!(earlier, [1; 100]);
+        drop(spool);
+
+        let spool = store.open(&fingerprint("b")).await.unwrap();
+        assert_eq!(spool.offset(), 0);
+    }
+
+    #[tokio::test]
+    async fn an_offer_is_only_received_once_at_a_time() {
+        let dir = TempDir::new().unwrap();
+        let store = ResumeStore::new(dir.path().to_path_buf());
+
+        let spool = store.open(&fingerprint("a")).await.unwrap();
+        let err = store.open(&fingerprint("a")).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        spool.remove().await;
+        assert!(store.open(&fingerprint("a")).await.is_ok());
+    }
+}
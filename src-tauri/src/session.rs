@@ -0,0 +1,368 @@
+use crate::error::Error;
+use crate::progress::Direction;
+use crate::pylon::Pylon;
+use crate::transfer::{Incoming, Offer};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::task::AbortHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// The number of finished sessions kept, before the oldest are removed.
+const MAX_FINISHED_SESSIONS: usize = 50;
+
+/// Identifies a transfer session.
+pub type SessionId = u64;
+
+/// The lifecycle state of a transfer session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionState {
+    /// A Pylon code is being generated.
+    Generating,
+    /// Waiting for the peer to connect.
+    Waiting,
+    /// Data is being transferred.
+    Transferring,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl SessionState {
+    /// Indicates if the session has finished, one way or another.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, Self::Done | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// A description of a transfer session, as exposed to the frontend.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub direction: Direction,
+    pub state: SessionState,
+    pub code: Option<String>,
+    pub paths: Vec<PathBuf>,
+    pub bytes_transferred: u64,
+    pub error: Option<String>,
+}
+
+/// A transfer session, along with the resources it owns.
+struct Session {
+    info: SessionInfo,
+    pylon: Option<Pylon>,
+    incoming: Option<Incoming>,
+    cancel: CancellationToken,
+    task: Option<AbortHandle>,
+}
+
+/// Keeps track of all transfer sessions, keyed by their ID.
+#[derive(Default)]
+pub struct SessionManager {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<SessionId, Session>>,
+}
+
+impl SessionManager {
+    /// Creates a new session.
+    ///
+    /// Makes room for it by removing the oldest finished sessions, beyond those that are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the transfer.
+    /// * `state` - The initial state of the session.
+    pub fn create(&self, direction: Direction, state: SessionState) -> SessionId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let session = Session {
+            info: SessionInfo {
+                id,
+                direction,
+                state,
+                code: None,
+                paths: Vec::new(),
+                bytes_transferred: 0,
+                error: None,
+            },
+            pylon: None,
+            incoming: None,
+            cancel: CancellationToken::new(),
+            task: None,
+        };
+
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        sessions.insert(id, session);
+        tracing::info!(session = id, ?direction, "session created");
+
+        id
+    }
+
+    /// Returns a description of every session.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let sessions = self.sessions.lock().unwrap();
+        let mut infos: Vec<_> = sessions.values().map(|s| s.info.clone()).collect();
+        infos.sort_by_key(|info| info.id);
+
+        infos
+    }
+
+    /// Returns a description of the given session.
+    pub fn get(&self, id: SessionId) -> Result<SessionInfo, Error> {
+        self.with_session(id, |session| Ok(session.info.clone()))
+    }
+
+    /// Returns the token used to cancel the given session.
+    pub fn cancel_token(&self, id: SessionId) -> Result<CancellationToken, Error> {
+        self.with_session(id, |session| Ok(session.cancel.clone()))
+    }
+
+    /// Stores the generated code and its Pylon, and starts waiting for the peer.
+    ///
+    /// Fails if the session was cancelled in the meantime, in which case the Pylon is dropped.
+    pub fn set_code(&self, id: SessionId, code: String, pylon: Pylon) -> Result<(), Error> {
+        self.with_session(id, |session| {
+            if session.info.state.is_terminal() {
+                return Err(Error::Cancelled);
+            }
+
+            session.info.state = SessionState::Waiting;
+            session.info.code = Some(code);
+            session.pylon = Some(pylon);
+
+            Ok(())
+        })
+    }
+
+    /// Takes the Pylon out of the given session, so that it can be used for a transfer.
+    pub fn take_pylon(&self, id: SessionId, paths: Vec<PathBuf>) -> Result<Pylon, Error> {
+        self.with_session(id, |session| {
+            let pylon = session.pylon.take().ok_or(Error::NoPendingCode)?;
+            session.info.paths = paths;
+
+            Ok(pylon)
+        })
+    }
+
+    /// Stores the offer received from the peer, which holds on to the Pylon it came through.
+    pub fn set_incoming(&self, id: SessionId, incoming: Incoming) -> Result<(), Error> {
+        self.with_session(id, |session| {
+            if session.info.state.is_terminal() {
+                return Err(Error::Cancelled);
+            }
+
+            session.incoming = Some(incoming);
+
+            Ok(())
+        })
+    }
+
+    /// Describes the pending offer of the given session.
+    pub fn offer(&self, id: SessionId) -> Result<Offer, Error> {
+        self.with_session(id, |session| {
+            let incoming = session.incoming.as_ref().ok_or(Error::NoPendingOffer)?;
+
+            Ok(incoming.offer().clone())
+        })
+    }
+
+    /// Takes the pending offer out of the given session, so that it can be accepted or rejected.
+    pub fn take_incoming(&self, id: SessionId) -> Result<Incoming, Error> {
+        self.with_session(id, |session| {
+            let incoming = session.incoming.take().ok_or(Error::NoPendingOffer)?;
+            session.info.state = SessionState::Transferring;
+
+            Ok(incoming)
+        })
+    }
+
+    /// Records the number of bytes transferred so far.
+    ///
+    /// A send that was waiting for its peer starts transferring as soon as any progress is made,
+    /// whereas a receive only starts once its offer has been accepted.
+    pub fn set_bytes_transferred(&self, id: SessionId, bytes: u64) {
+        let _ = self.with_session(id, |session| {
+            session.info.bytes_transferred = bytes;
+            if session.info.direction == Direction::Send
+                && session.info.state == SessionState::Waiting
+            {
+                session.info.state = SessionState::Transferring;
+            }
+
+            Ok(())
+        });
+    }
+
+    /// Records the outcome of a session, unless it has already finished.
+    pub fn finish<T>(&self, id: SessionId, result: &Result<T, Error>) {
+        let _ = self.with_session(id, |session| {
+            if session.info.state.is_terminal() {
+                return Ok(());
+            }
+
+            session.info.state = match result {
+                Ok(_) => SessionState::Done,
+                Err(Error::Cancelled) => SessionState::Cancelled,
+                Err(err) => {
+                    // Only the code and details, as some messages come from Pylon itself.
+                    tracing::warn!(
+                        session = id,
+                        code = err.code(),
+                        details = ?err.details(),
+                        "session failed"
+                    );
+                    session.info.error = Some(err.to_string());
+                    SessionState::Failed
+                }
+            };
+            tracing::info!(
+                session = id,
+                state = ?session.info.state,
+                bytes = session.info.bytes_transferred,
+                "session finished"
+            );
+            session.pylon = None;
+            session.incoming = None;
+            session.task = None;
+
+            Ok(())
+        });
+    }
+
+    /// Cancels the given session.
+    ///
+    /// Transfers are given the chance to notify their peer, while code generation is aborted
+    /// outright. Either way, the session's Pylon is dropped, which closes its mailbox connection.
+    pub fn cancel(&self, id: SessionId) -> Result<(), Error> {
+        self.with_session(id, |session| {
+            if session.info.state.is_terminal() {
+                return Ok(());
+            }
+
+            session.cancel.cancel();
+            if session.info.state == SessionState::Generating {
+                if let Some(task) = session.task.take() {
+                    task.abort();
+                }
+            }
+
+            tracing::info!(session = id, "session cancelled");
+            session.info.state = SessionState::Cancelled;
+            session.info.code = None;
+            session.pylon = None;
+            session.incoming = None;
+
+            Ok(())
+        })
+    }
+
+    /// Runs the work for a session as a separate task, which can be aborted by `cancel`.
+    ///
+    /// Everything logged by the work is tagged with the session's ID.
+    pub async fn run<T, F>(&self, id: SessionId, work: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let task = tokio::spawn(work.instrument(tracing::info_span!("session", id)));
+        let _ = self.with_session(id, |session| {
+            session.task = Some(task.abort_handle());
+            Ok(())
+        });
+
+        match task.await {
+            Ok(result) => result,
+            Err(err) if err.is_cancelled() => Err(Error::Cancelled),
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    fn with_session<T>(
+        &self,
+        id: SessionId,
+        f: impl FnOnce(&mut Session) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&id).ok_or(Error::UnknownSession(id))?;
+
+        f(session)
+    }
+}
+
+/// Removes the oldest finished sessions, beyond the number that are kept.
+fn prune(sessions: &mut HashMap<SessionId, Session>) {
+    let mut finished: Vec<_> = sessions
+        .values()
+        .filter(|session| session.info.state.is_terminal())
+        .map(|session| session.info.id)
+        .collect();
+    if finished.len() <= MAX_FINISHED_SESSIONS {
+        return;
+    }
+
+    finished.sort_unstable();
+    for id in &finished[..finished.len() - MAX_FINISHED_SESSIONS] {
+        sessions.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(manager: &SessionManager, state: SessionState) -> SessionId {
+        let id = manager.create(Direction::Send, SessionState::Waiting);
+        let result = match state {
+            SessionState::Done => Ok(()),
+            SessionState::Cancelled => Err(Error::Cancelled),
+            _ => Err(Error::NoPendingCode),
+        };
+        manager.finish(id, &result);
+
+        id
+    }
+
+    #[test]
+    fn finished_sessions_are_capped() {
+        let manager = SessionManager::default();
+        let first = finished(&manager, SessionState::Done);
+        for _ in 0..MAX_FINISHED_SESSIONS {
+            finished(&manager, SessionState::Failed);
+        }
+        let last = finished(&manager, SessionState::Cancelled);
+
+        let ids: Vec<_> = manager.list().iter().map(|info| info.id).collect();
+        assert_eq!(ids.len(), MAX_FINISHED_SESSIONS + 1);
+        assert!(!ids.contains(&first));
+        assert!(ids.contains(&last));
+        assert!(matches!(manager.get(first), Err(Error::UnknownSession(id)) if id == first));
+    }
+
+    #[test]
+    fn unfinished_sessions_are_kept() {
+        let manager = SessionManager::default();
+        let waiting = manager.create(Direction::Receive, SessionState::Waiting);
+        for _ in 0..=MAX_FINISHED_SESSIONS + 1 {
+            finished(&manager, SessionState::Done);
+        }
+
+        assert_eq!(manager.get(waiting).unwrap().state, SessionState::Waiting);
+    }
+
+    #[test]
+    fn cancelled_sessions_keep_no_offer() {
+        let manager = SessionManager::default();
+        let id = manager.create(Direction::Receive, SessionState::Waiting);
+        manager.cancel(id).unwrap();
+
+        assert_eq!(manager.get(id).unwrap().state, SessionState::Cancelled);
+        assert!(matches!(manager.offer(id), Err(Error::NoPendingOffer)));
+        assert!(manager.cancel_token(id).unwrap().is_cancelled());
+    }
+}
@@ -0,0 +1,481 @@
+use crate::code;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use url::Url;
+
+/// The name of the settings file, within the app's config directory.
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// The current version of the settings schema.
+const SETTINGS_VERSION: u64 = 1;
+
+/// Upgrades stored settings from one version of the schema to the next.
+type Migration = fn(&mut Map<String, Value>);
+
+/// Migrations between versions of the settings schema.
+///
+/// The migration at index `i` upgrades settings from version `i + 1` to version `i + 2`. Once
+/// released, a migration must never change; add a new one instead.
+const MIGRATIONS: &[Migration] = &[];
+
+/// The schemes a rendezvous server URL may use.
+const RENDEZVOUS_SCHEMES: &[&str] = &["ws", "wss"];
+
+/// The schemes a transit relay URL may use.
+const RELAY_SCHEMES: &[&str] = &["tcp", "ws", "wss"];
+
+/// The theme to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+/// What to do when a received file or folder has the same name as an existing one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CollisionPolicy {
+    /// Save it under a new name, such as `photo (1).jpg`.
+    #[default]
+    Rename,
+    Overwrite,
+    /// Don't save it at all.
+    Skip,
+    /// Let the user decide, for each transfer.
+    Ask,
+}
+
+/// How much detail goes into the log files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+/// User settings.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub theme: Theme,
+    pub language: String,
+    pub code_length: usize,
+    /// Where received files are saved. `None` means the OS's downloads directory.
+    pub download_dir: Option<PathBuf>,
+    pub collision_policy: CollisionPolicy,
+    /// The rendezvous server to use. `None` means Pylon's default server.
+    pub rendezvous_url: Option<String>,
+    /// The transit relays to use, in order of preference.
+    pub relay_urls: Vec<String>,
+    /// Whether the public relay may be used, after the ones above.
+    pub use_default_relay: bool,
+    pub log_level: LogLevel,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            language: "en".to_string(),
+            code_length: code::DEFAULT_CODE_LENGTH,
+            download_dir: None,
+            collision_policy: CollisionPolicy::default(),
+            rendezvous_url: None,
+            relay_urls: Vec::new(),
+            use_default_relay: true,
+            log_level: LogLevel::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Checks that the settings make sense.
+    fn validate(&self) -> Result<(), Error> {
+        code::validate_length(self.code_length)?;
+        if let Some(url) = &self.rendezvous_url {
+            validate_rendezvous_url(url)?;
+        }
+        for url in &self.relay_urls {
+            validate_relay_url(url)?;
+        }
+        if self
+            .download_dir
+            .as_ref()
+            .is_some_and(|dir| !dir.is_absolute())
+        {
+            return Err(Error::InvalidSettings(
+                "the download directory must be an absolute path".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the directory received files are saved in by default.
+    pub fn download_dir(&self) -> Result<PathBuf, Error> {
+        self.download_dir
+            .clone()
+            .or_else(tauri::api::path::download_dir)
+            .ok_or(Error::NoDownloadDir)
+    }
+}
+
+/// The settings as stored on disk.
+#[derive(Serialize)]
+struct SettingsFile<'a> {
+    version: u64,
+    #[serde(flatten)]
+    settings: &'a Settings,
+}
+
+/// Loads and persists user settings.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: RwLock<Settings>,
+}
+
+impl SettingsStore {
+    /// Loads the settings from the given file, migrating them to the current schema if needed.
+    ///
+    /// A missing or unreadable file results in the default settings, so that a broken settings
+    /// file can never keep the app from starting.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the settings file.
+    pub fn load(path: PathBuf) -> Self {
+        let settings = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .and_then(|value| migrate(value, MIGRATIONS).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    /// Returns the current settings.
+    pub fn get(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Updates some of the settings, and persists them.
+    ///
+    /// Returns the updated settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `changes` - The settings to change, as a partial settings object.
+    pub fn update(&self, changes: Map<String, Value>) -> Result<Settings, Error> {
+        let mut settings = self.settings.write().unwrap();
+
+        let Value::Object(mut merged) = serde_json::to_value(&*settings)
+            .map_err(|err| Error::InvalidSettings(err.to_string()))?
+        else {
+            unreachable!("settings always serialize to an object");
+        };
+        merged.extend(changes);
+
+        let updated: Settings = serde_json::from_value(Value::Object(merged))
+            .map_err(|err| Error::InvalidSettings(err.to_string()))?;
+        updated.validate()?;
+
+        save(&self.path, &updated)?;
+        *settings = updated.clone();
+
+        Ok(updated)
+    }
+}
+
+/// Checks that the given URL can be used as a rendezvous server.
+///
+/// # Arguments
+///
+/// * `url` - The URL to check.
+pub fn validate_rendezvous_url(url: &str) -> Result<(), Error> {
+    validate_server_url(url, RENDEZVOUS_SCHEMES).map(|_| ())
+}
+
+/// Checks that the given URL can be used as a transit relay.
+///
+/// TCP relays have no default port, so theirs must be given explicitly.
+///
+/// # Arguments
+///
+/// * `url` - The URL to check.
+pub fn validate_relay_url(url: &str) -> Result<(), Error> {
+    let parsed = validate_server_url(url, RELAY_SCHEMES)?;
+    if parsed.scheme() == "tcp" && parsed.port().is_none() {
+        return Err(Error::InvalidServerUrl {
+            url: url.to_string(),
+            reason: "missing port".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the given URL has a host, and one of the given schemes, returning it parsed.
+fn validate_server_url(url: &str, schemes: &[&str]) -> Result<Url, Error> {
+    let invalid = |reason: String| Error::InvalidServerUrl {
+        url: url.to_string(),
+        reason,
+    };
+
+    let parsed = Url::parse(url).map_err(|err| invalid(err.to_string()))?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(invalid(format!(
+            "scheme must be one of {}",
+            schemes.join(", ")
+        )));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("missing host".to_string()));
+    }
+
+    Ok(parsed)
+}
+
+/// Brings stored settings up to date with the current schema.
+///
+/// Settings from a newer version of the app are read as is, ignoring anything we don't know of.
+///
+/// # Arguments
+///
+/// * `value` - The stored settings.
+/// * `migrations` - The migrations between versions of the schema, see [`MIGRATIONS`].
+fn migrate(value: Value, migrations: &[Migration]) -> serde_json::Result<Settings> {
+    let Value::Object(mut map) = value else {
+        return serde_json::from_value(value);
+    };
+
+    let version = map.remove("version").and_then(|v| v.as_u64()).unwrap_or(1);
+    for migration in migrations.iter().skip(version.saturating_sub(1) as usize) {
+        migration(&mut map);
+    }
+
+    serde_json::from_value(Value::Object(map))
+}
+
+/// Writes the settings to the given file.
+///
+/// The settings are written to a temporary file first, so that a crash can't leave a
+/// half-written settings file behind.
+fn save(path: &Path, settings: &Settings) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let file = SettingsFile {
+        version: SETTINGS_VERSION,
+        settings,
+    };
+    let data = serde_json::to_vec_pretty(&file)?;
+
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, data)?;
+    std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn changes(changes: Value) -> Map<String, Value> {
+        let Value::Object(changes) = changes else {
+            panic!("expected an object");
+        };
+
+        changes
+    }
+
+    fn to_value(settings: &Settings) -> Value {
+        serde_json::to_value(settings).unwrap()
+    }
+
+    #[test]
+    fn load_falls_back_to_defaults_for_missing_or_corrupt_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(SETTINGS_FILE_NAME);
+        let defaults = to_value(&Settings::default());
+
+        assert_eq!(to_value(&SettingsStore::load(path.clone()).get()), defaults);
+
+        for corrupt in [
+            "",
+            "{\"version\": 1, \"theme\": ",
+            "[]",
+            "{\"codeLength\": \"four\"}",
+        ] {
+            std::fs::write(&path, corrupt).unwrap();
+            assert_eq!(to_value(&SettingsStore::load(path.clone()).get()), defaults);
+        }
+    }
+
+    #[test]
+    fn updates_are_saved_and_loaded_again() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config").join(SETTINGS_FILE_NAME);
+        let store = SettingsStore::load(path.clone());
+
+        let updated = store
+            .update(changes(
+                json!({ "codeLength": 4, "relayUrls": ["tcp://relay.example.com:4001"] }),
+            ))
+            .unwrap();
+        assert_eq!(updated.code_length, 4);
+
+        let saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], SETTINGS_VERSION);
+
+        let loaded = SettingsStore::load(path).get();
+        assert_eq!(to_value(&loaded), to_value(&updated));
+    }
+
+    #[test]
+    fn update_rejects_invalid_changes_without_writing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(SETTINGS_FILE_NAME);
+        let store = SettingsStore::load(path.clone());
+
+        let invalid = [
+            json!({ "codeLength": 9 }),
+            json!({ "codeLength": "four" }),
+            json!({ "theme": "purple" }),
+            json!({ "relayUrls": ["tcp://relay.example.com"] }),
+            json!({ "rendezvousUrl": "http://relay.example.com" }),
+            json!({ "downloadDir": "downloads" }),
+        ];
+        for change in &invalid {
+            assert!(store.update(changes(change.clone())).is_err(), "{change}");
+        }
+        assert!(!path.exists());
+        assert_eq!(to_value(&store.get()), to_value(&Settings::default()));
+
+        store.update(changes(json!({ "codeLength": 4 }))).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        for change in &invalid {
+            assert!(store.update(changes(change.clone())).is_err(), "{change}");
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), saved);
+        assert_eq!(store.get().code_length, 4);
+    }
+
+    #[test]
+    fn every_migration_leads_up_to_the_current_version() {
+        assert_eq!(MIGRATIONS.len() as u64 + 1, SETTINGS_VERSION);
+    }
+
+    #[test]
+    fn migrate_upgrades_older_versions() {
+        // Version 2 renamed `length` to `codeLength`, and version 3 doubled it.
+        let migrations: &[Migration] = &[
+            |settings| {
+                if let Some(length) = settings.remove("length") {
+                    settings.insert("codeLength".to_string(), length);
+                }
+            },
+            |settings| {
+                let length = settings["codeLength"].as_u64().unwrap();
+                settings.insert("codeLength".to_string(), json!(length * 2));
+            },
+        ];
+
+        let from_v1 = migrate(json!({ "version": 1, "length": 2 }), migrations).unwrap();
+        assert_eq!(from_v1.code_length, 4);
+
+        let unversioned = migrate(json!({ "length": 2 }), migrations).unwrap();
+        assert_eq!(unversioned.code_length, 4);
+
+        let from_v2 = migrate(json!({ "version": 2, "codeLength": 3 }), migrations).unwrap();
+        assert_eq!(from_v2.code_length, 6);
+
+        let current = migrate(json!({ "version": 3, "codeLength": 3 }), migrations).unwrap();
+        assert_eq!(current.code_length, 3);
+    }
+
+    #[test]
+    fn migrate_reads_newer_versions_as_is() {
+        let settings = json!({
+            "version": SETTINGS_VERSION + 1,
+            "codeLength": 5,
+            "somethingNew": true,
+        });
+
+        let migrated = migrate(settings, &[|_| panic!("nothing to migrate")]).unwrap();
+        assert_eq!(migrated.code_length, 5);
+    }
+
+    fn reason(result: Result<(), Error>) -> String {
+        match result {
+            Err(Error::InvalidServerUrl { reason, .. }) => reason,
+            other => panic!("expected an invalid server URL, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rendezvous_url_accepts_websockets() {
+        assert!(validate_rendezvous_url("ws://relay.example.com:4000/v1").is_ok());
+        assert!(validate_rendezvous_url("wss://relay.example.com/v1").is_ok());
+        assert!(validate_rendezvous_url("ws://127.0.0.1:4000").is_ok());
+    }
+
+    #[test]
+    fn rendezvous_url_rejects_other_schemes() {
+        let reason = reason(validate_rendezvous_url("http://relay.example.com/v1"));
+        assert_eq!(reason, "scheme must be one of ws, wss");
+
+        assert!(validate_rendezvous_url("tcp://relay.example.com:4001").is_err());
+    }
+
+    #[test]
+    fn rendezvous_url_rejects_missing_host() {
+        assert_eq!(reason(validate_rendezvous_url("ws://")), "empty host");
+        assert_eq!(
+            reason(validate_rendezvous_url("wss://:4000/v1")),
+            "empty host"
+        );
+    }
+
+    #[test]
+    fn rendezvous_url_rejects_garbage() {
+        assert!(validate_rendezvous_url("").is_err());
+        assert!(validate_rendezvous_url("not a url").is_err());
+        assert!(validate_rendezvous_url("relay.example.com:4000").is_err());
+        assert!(validate_rendezvous_url("ws://[::1").is_err());
+    }
+
+    #[test]
+    fn relay_url_accepts_tcp_and_websockets() {
+        assert!(validate_relay_url("tcp://relay.example.com:4001").is_ok());
+        assert!(validate_relay_url("ws://relay.example.com/").is_ok());
+        assert!(validate_relay_url("wss://relay.example.com:443/").is_ok());
+    }
+
+    #[test]
+    fn relay_url_requires_a_port_for_tcp() {
+        let reason = reason(validate_relay_url("tcp://relay.example.com"));
+        assert_eq!(reason, "missing port");
+    }
+
+    #[test]
+    fn relay_url_rejects_missing_host() {
+        assert_eq!(reason(validate_relay_url("tcp:///")), "missing host");
+        assert_eq!(reason(validate_relay_url("tcp:4001")), "missing host");
+    }
+}
@@ -0,0 +1,602 @@
+use fs4::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The name of the file the running instance holds a lock on, within the app's data directory.
+pub const LOCK_FILE_NAME: &str = "instance.lock";
+
+/// The name of the socket the running instance listens on, within the app's data directory.
+#[cfg(unix)]
+const SOCKET_FILE_NAME: &str = "instance.sock";
+
+/// Bumped whenever the format of forwarded messages changes.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// The largest message we're willing to read. Arguments are never anywhere near this.
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024;
+
+/// Sent back once forwarded arguments have been handled.
+const ACK: u8 = 1;
+
+/// How long a connected instance has to send its arguments.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many times to try reaching the running instance, which may still be starting up.
+const CONNECT_ATTEMPTS: u32 = 10;
+
+/// How long to wait between attempts to reach the running instance.
+const CONNECT_DELAY: Duration = Duration::from_millis(200);
+
+/// The arguments of a later launch, as sent to the running instance.
+#[derive(Serialize, Deserialize)]
+struct Forwarded {
+    version: u32,
+    args: Vec<String>,
+}
+
+/// Writes the given arguments as a single message.
+///
+/// Messages are a big-endian `u32` length, followed by that many bytes of JSON.
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the message.
+/// * `args` - The arguments to forward.
+pub async fn write_args<W>(writer: &mut W, args: &[String]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let message = serde_json::to_vec(&Forwarded {
+        version: PROTOCOL_VERSION,
+        args: args.to_vec(),
+    })?;
+    let len = u32::try_from(message.len())
+        .ok()
+        .filter(|len| *len <= MAX_MESSAGE_SIZE)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too many arguments"))?;
+
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(&message).await?;
+    writer.flush().await
+}
+
+/// Reads a single message written by `write_args`, returning its arguments.
+///
+/// # Arguments
+///
+/// * `reader` - Where to read the message from.
+pub async fn read_args<R>(reader: &mut R) -> io::Result<Vec<String>>
+where
+    R: AsyncRead + Unpin,
+{
+    let len = reader.read_u32().await?;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message too large: {len} bytes"),
+        ));
+    }
+
+    let mut message = vec![0; len as usize];
+    reader.read_exact(&mut message).await?;
+    let forwarded: Forwarded = serde_json::from_slice(&message)?;
+    if forwarded.version != PROTOCOL_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported protocol version {}", forwarded.version),
+        ));
+    }
+
+    Ok(forwarded.args)
+}
+
+/// Where the running instance can be reached.
+#[cfg(unix)]
+fn endpoint(data_dir: &Path) -> PathBuf {
+    data_dir.join(SOCKET_FILE_NAME)
+}
+
+/// Where the running instance can be reached.
+///
+/// Pipes aren't files, so they're named after the app and the user instead.
+#[cfg(windows)]
+fn endpoint(_data_dir: &Path) -> PathBuf {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    PathBuf::from(format!(r"\\.\pipe\{}-{user}", crate::APP_ID))
+}
+
+/// Which instance of the app we are.
+pub enum Instance {
+    /// We're the only instance, and should listen for later launches with `Primary::serve`.
+    Primary(Primary),
+    /// Another instance is running, and we should forward our arguments to it with
+    /// `Secondary::forward`.
+    Secondary(Secondary),
+}
+
+impl Instance {
+    /// Finds out whether another instance is running, by trying to lock a file in the app's data
+    /// directory.
+    ///
+    /// The lock is held for as long as the primary instance lives, and released by the OS even if
+    /// it crashes.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_dir` - The app's data directory.
+    pub fn acquire(data_dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(data_dir.join(LOCK_FILE_NAME))?;
+        let endpoint = endpoint(data_dir);
+
+        match lock.try_lock_exclusive() {
+            Ok(()) => Ok(Self::Primary(Primary {
+                _lock: lock,
+                endpoint,
+            })),
+            Err(err) if err.kind() == fs4::lock_contended_error().kind() => {
+                Ok(Self::Secondary(Secondary { endpoint }))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The only running instance of the app.
+pub struct Primary {
+    _lock: File,
+    endpoint: PathBuf,
+}
+
+impl Primary {
+    /// Listens for later launches, calling the handler with the arguments each one forwards.
+    ///
+    /// Only returns if listening fails. A misbehaving launch only fails its own connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Called with the arguments of each later launch.
+    #[cfg(unix)]
+    pub async fn serve<F>(self, handler: F) -> io::Result<()>
+    where
+        F: Fn(Vec<String>) + Clone + Send + Sync + 'static,
+    {
+        // A socket left behind by an instance that crashed would keep us from binding.
+        match fs::remove_file(&self.endpoint) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        let listener = tokio::net::UnixListener::bind(&self.endpoint)?;
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(handle(stream, handler.clone()));
+        }
+    }
+
+    /// Listens for later launches, calling the handler with the arguments each one forwards.
+    ///
+    /// Only returns if listening fails. A misbehaving launch only fails its own connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - Called with the arguments of each later launch.
+    #[cfg(windows)]
+    pub async fn serve<F>(self, handler: F) -> io::Result<()>
+    where
+        F: Fn(Vec<String>) + Clone + Send + Sync + 'static,
+    {
+        let security = PipeSecurity::new()?;
+        let mut server = security.create(&self.endpoint, true)?;
+
+        loop {
+            server.connect().await?;
+            // Each pipe instance serves a single client, so make the next one before handling
+            // this one.
+            let connected = server;
+            server = security.create(&self.endpoint, false)?;
+            tokio::spawn(handle(connected, handler.clone()));
+        }
+    }
+}
+
+/// Reads the arguments forwarded over a single connection, and acknowledges them once handled.
+async fn handle<S, F>(mut stream: S, handler: F)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: Fn(Vec<String>),
+{
+    let result = async {
+        let args = tokio::time::timeout(READ_TIMEOUT, read_args(&mut stream))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no arguments were sent"))??;
+        handler(args);
+        stream.write_u8(ACK).await
+    }
+    .await;
+
+    if let Err(err) = result {
+        tracing::warn!("could not handle a forwarded launch: {err}");
+    }
+}
+
+/// A later launch of the app, while another instance is running.
+pub struct Secondary {
+    endpoint: PathBuf,
+}
+
+impl Secondary {
+    /// Forwards the given arguments to the running instance, and waits for it to handle them.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command-line arguments, without the program name.
+    pub async fn forward(&self, args: &[String]) -> io::Result<()> {
+        let mut stream = retry(|| connect(&self.endpoint)).await?;
+        #[cfg(windows)]
+        check_server_user(&stream)?;
+        write_args(&mut stream, args).await?;
+
+        if stream.read_u8().await? == ACK {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected reply from the running instance",
+            ))
+        }
+    }
+}
+
+/// Connects to the running instance.
+#[cfg(unix)]
+async fn connect(endpoint: &Path) -> io::Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(endpoint).await
+}
+
+/// Connects to the running instance.
+#[cfg(windows)]
+async fn connect(endpoint: &Path) -> io::Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(endpoint)
+}
+
+/// Who may connect to the pipe the running instance listens on: only the current user, and only
+/// from this machine.
+#[cfg(windows)]
+struct PipeSecurity {
+    descriptor: windows_sys::Win32::Security::PSECURITY_DESCRIPTOR,
+}
+
+// The descriptor is only read once made, and freed along with its owner.
+#[cfg(windows)]
+unsafe impl Send for PipeSecurity {}
+
+#[cfg(windows)]
+impl PipeSecurity {
+    fn new() -> io::Result<Self> {
+        use windows_sys::Win32::Security::Authorization::{
+            ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+        };
+        use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+        // SAFETY: the pseudo handle of the current process is always valid.
+        let user = unsafe { process_user(GetCurrentProcess()) }?;
+
+        // A protected DACL, so that nothing is inherited, that gives the user full access, and
+        // nobody else any.
+        let sddl: Vec<u16> = format!("D:P(A;;GA;;;{user})")
+            .encode_utf16()
+            .chain([0])
+            .collect();
+        let mut descriptor = std::ptr::null_mut();
+        // SAFETY: the string is null-terminated, and the descriptor is freed on drop.
+        let converted = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            )
+        };
+        if converted == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { descriptor })
+    }
+
+    /// Creates an instance of the pipe.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The name of the pipe.
+    /// * `first` - Whether this is the first instance, which fails if the pipe already exists.
+    fn create(
+        &self,
+        endpoint: &Path,
+        first: bool,
+    ) -> io::Result<tokio::net::windows::named_pipe::NamedPipeServer> {
+        use windows_sys::Win32::Security::SECURITY_ATTRIBUTES;
+
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.descriptor,
+            bInheritHandle: 0,
+        };
+        // SAFETY: the attributes, and the descriptor they point to, outlive the call.
+        unsafe {
+            tokio::net::windows::named_pipe::ServerOptions::new()
+                .first_pipe_instance(first)
+                .reject_remote_clients(true)
+                .create_with_security_attributes_raw(
+                    endpoint,
+                    (&mut attributes as *mut SECURITY_ATTRIBUTES).cast(),
+                )
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PipeSecurity {
+    fn drop(&mut self) {
+        // SAFETY: the descriptor was allocated with `LocalAlloc`, and is no longer used.
+        unsafe { windows_sys::Win32::Foundation::LocalFree(self.descriptor) };
+    }
+}
+
+/// Makes sure the running instance we're connected to belongs to the current user.
+///
+/// Any user could have created a pipe by the same name, and would then get our arguments, which
+/// may include a Pylon code.
+#[cfg(windows)]
+fn check_server_user(pipe: &tokio::net::windows::named_pipe::NamedPipeClient) -> io::Result<()> {
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use windows_sys::Win32::System::Pipes::GetNamedPipeServerProcessId;
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    let mut server_pid = 0;
+    // SAFETY: the pipe's handle is valid for as long as the pipe.
+    if unsafe { GetNamedPipeServerProcessId(pipe.as_raw_handle() as _, &mut server_pid) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: a handle that was opened is closed once dropped, and the pseudo handle of the
+    // current process is always valid.
+    let (server_user, user) = unsafe {
+        let server = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, server_pid);
+        if server == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let server = OwnedHandle::from_raw_handle(server as _);
+
+        (
+            process_user(server.as_raw_handle() as _)?,
+            process_user(GetCurrentProcess())?,
+        )
+    };
+
+    if server_user != user {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the running instance belongs to another user",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the security identifier of the user the given process runs as, such as
+/// `S-1-5-21-...-1001`.
+///
+/// # Safety
+///
+/// The handle must be a valid process handle, with at least the
+/// `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+#[cfg(windows)]
+unsafe fn process_user(process: windows_sys::Win32::Foundation::HANDLE) -> io::Result<String> {
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Authorization::ConvertSidToStringSidW;
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenUser, TOKEN_QUERY, TOKEN_USER};
+    use windows_sys::Win32::System::Threading::OpenProcessToken;
+
+    let mut token = 0;
+    if OpenProcessToken(process, TOKEN_QUERY, &mut token) == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let token = OwnedHandle::from_raw_handle(token as _);
+
+    // The first call only finds out how large the information is.
+    let mut len = 0;
+    GetTokenInformation(
+        token.as_raw_handle() as _,
+        TokenUser,
+        std::ptr::null_mut(),
+        0,
+        &mut len,
+    );
+    // As `u64`s, so that the `TOKEN_USER` the information starts with is aligned.
+    let mut information = vec![0u64; (len as usize).div_ceil(8)];
+    if GetTokenInformation(
+        token.as_raw_handle() as _,
+        TokenUser,
+        information.as_mut_ptr().cast(),
+        len,
+        &mut len,
+    ) == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    let sid = (*information.as_ptr().cast::<TOKEN_USER>()).User.Sid;
+
+    let mut string_sid = std::ptr::null_mut();
+    if ConvertSidToStringSidW(sid, &mut string_sid) == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let len = (0..).take_while(|&i| *string_sid.add(i) != 0).count();
+    let user = String::from_utf16_lossy(std::slice::from_raw_parts(string_sid, len));
+    LocalFree(string_sid.cast());
+
+    Ok(user)
+}
+
+/// Retries the given connection a few times, since the running instance may have taken the lock
+/// but not started listening yet, or be busy with another launch.
+async fn retry<T, F, Fut>(mut connect: F) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match connect().await {
+            Ok(stream) => return Ok(stream),
+            Err(err) if attempt >= CONNECT_ATTEMPTS => return Err(err),
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(CONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    async fn write_raw<W: AsyncWrite + Unpin>(writer: &mut W, message: &[u8]) {
+        writer.write_u32(message.len() as u32).await.unwrap();
+        writer.write_all(message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn args_round_trip() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let sent = args(&["--minimized", "pylon://receive?code=7-guitarist-revenge"]);
+
+        write_args(&mut client, &sent).await.unwrap();
+        assert_eq!(read_args(&mut server).await.unwrap(), sent);
+
+        write_args(&mut client, &[]).await.unwrap();
+        assert!(read_args(&mut server).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn write_rejects_oversized_args() {
+        let (mut client, _server) = tokio::io::duplex(1024);
+        let sent = vec!["a".repeat(MAX_MESSAGE_SIZE as usize)];
+
+        let err = write_args(&mut client, &sent).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn read_rejects_oversized_messages() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_u32(MAX_MESSAGE_SIZE + 1).await.unwrap();
+
+        let err = read_args(&mut server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn read_rejects_other_versions() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        write_raw(&mut client, br#"{"version":2,"args":[]}"#).await;
+
+        let err = read_args(&mut server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn read_rejects_malformed_messages() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        write_raw(&mut client, b"not json").await;
+
+        let err = read_args(&mut server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn read_fails_on_truncated_messages() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_u32(16).await.unwrap();
+        client.write_all(b"{}").await.unwrap();
+        drop(client);
+
+        let err = read_args(&mut server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn handle_acknowledges_once_handled() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handler = {
+            let received = received.clone();
+            move |args| received.lock().unwrap().push(args)
+        };
+        let sent = args(&["pylon://receive?code=7-guitarist-revenge"]);
+
+        let task = tokio::spawn(handle(server, handler));
+        write_args(&mut client, &sent).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), ACK);
+        task.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![sent]);
+    }
+
+    #[tokio::test]
+    async fn handle_ignores_bad_messages() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let handled = Arc::new(Mutex::new(false));
+        let handler = {
+            let handled = handled.clone();
+            move |_| *handled.lock().unwrap() = true
+        };
+
+        let task = tokio::spawn(handle(server, handler));
+        write_raw(&mut client, br#"{"version":2,"args":[]}"#).await;
+        task.await.unwrap();
+
+        assert!(client.read_u8().await.is_err());
+        assert!(!*handled.lock().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn later_launches_forward_to_the_primary() {
+        let data_dir = tempfile::TempDir::new().unwrap();
+
+        let Instance::Primary(primary) = Instance::acquire(data_dir.path()).unwrap() else {
+            panic!("the first instance should be the primary");
+        };
+        let Instance::Secondary(secondary) = Instance::acquire(data_dir.path()).unwrap() else {
+            panic!("a later instance should be a secondary");
+        };
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let server = tokio::spawn(primary.serve(move |args| sender.send(args).unwrap()));
+        let sent = args(&["pylon://receive?code=7-guitarist-revenge"]);
+        secondary.forward(&sent).await.unwrap();
+
+        assert_eq!(receiver.recv().await, Some(sent));
+        server.abort();
+    }
+}
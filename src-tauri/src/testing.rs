@@ -0,0 +1,239 @@
+//! Stand-ins for the servers a Pylon connects to, so that transfers can be tested locally.
+
+use async_tungstenite::tungstenite::Message;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
+
+/// The clients of a mailbox, and the messages they've added to it so far.
+#[derive(Default)]
+struct Mailbox {
+    messages: Vec<Value>,
+    clients: Vec<UnboundedSender<Value>>,
+}
+
+#[derive(Default)]
+struct Rendezvous {
+    nameplates: AtomicU64,
+    mailboxes: Mutex<HashMap<String, Mailbox>>,
+}
+
+/// Starts a rendezvous server, which allocates nameplates and passes messages between the
+/// clients of a mailbox like the public one does.
+///
+/// Returns the URL to connect to it with.
+pub async fn rendezvous_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/v1", listener.local_addr().unwrap());
+    let server = Arc::new(Rendezvous::default());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve_rendezvous(stream, server.clone()));
+        }
+    });
+
+    url
+}
+
+async fn serve_rendezvous(stream: TcpStream, server: Arc<Rendezvous>) {
+    let Ok(socket) = async_tungstenite::accept_async(stream.compat()).await else {
+        return;
+    };
+    let (mut sink, mut stream) = socket.split();
+    let (client, mut outgoing) = mpsc::unbounded_channel::<Value>();
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let _ = client.send(json!({ "type": "welcome", "welcome": {} }));
+    let mut side = Value::Null;
+    let mut mailbox = String::new();
+
+    while let Some(Ok(Message::Text(text))) = stream.next().await {
+        let Ok(message) = serde_json::from_str::<Value>(&text) else {
+            break;
+        };
+        let _ = client.send(json!({ "type": "ack" }));
+
+        let reply = match message["type"].as_str().unwrap_or_default() {
+            "bind" => {
+                side = message["side"].clone();
+                continue;
+            }
+            "list" => json!({ "type": "nameplates", "nameplates": [] }),
+            "allocate" => {
+                let nameplate = server.nameplates.fetch_add(1, Ordering::Relaxed) + 1;
+                json!({ "type": "allocated", "nameplate": nameplate.to_string() })
+            }
+            "claim" => {
+                let nameplate = message["nameplate"].as_str().unwrap_or_default();
+                json!({ "type": "claimed", "mailbox": format!("mailbox-{nameplate}") })
+            }
+            "release" => json!({ "type": "released" }),
+            "open" => {
+                mailbox = message["mailbox"].as_str().unwrap_or_default().to_string();
+                let mut mailboxes = server.mailboxes.lock().unwrap();
+                let mailbox = mailboxes.entry(mailbox.clone()).or_default();
+                for message in &mailbox.messages {
+                    let _ = client.send(message.clone());
+                }
+                mailbox.clients.push(client.clone());
+                continue;
+            }
+            "add" => {
+                let message = json!({
+                    "type": "message",
+                    "side": side,
+                    "phase": message["phase"],
+                    "body": message["body"],
+                });
+                let mut mailboxes = server.mailboxes.lock().unwrap();
+                let mailbox = mailboxes.entry(mailbox.clone()).or_default();
+                for client in &mailbox.clients {
+                    let _ = client.send(message.clone());
+                }
+                mailbox.messages.push(message);
+                continue;
+            }
+            "close" => json!({ "type": "closed" }),
+            "ping" => json!({ "type": "pong", "pong": message["ping"] }),
+            _ => continue,
+        };
+        let _ = client.send(reply);
+    }
+}
+
+#[derive(Default)]
+struct RelayState {
+    /// Connections waiting for the other side, keyed by their token.
+    waiting: Mutex<HashMap<String, (String, TcpStream)>>,
+    forwarded: AtomicU64,
+    limit: AtomicU64,
+}
+
+/// A transit relay, which pairs up the two sides of a transfer like the public one does.
+///
+/// It can also cut transfers off, to simulate a dropped connection.
+pub struct Relay {
+    pub url: String,
+    state: Arc<RelayState>,
+}
+
+impl Relay {
+    /// Starts the relay.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let state = Arc::new(RelayState {
+            limit: AtomicU64::new(u64::MAX),
+            ..RelayState::default()
+        });
+
+        tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve_relay(stream, state.clone()));
+                }
+            }
+        });
+
+        Self { url, state }
+    }
+
+    /// Cuts off every connection once the relay has forwarded this many bytes in total.
+    pub fn cut_off_after(&self, bytes: u64) {
+        self.state.limit.store(bytes, Ordering::Relaxed);
+    }
+
+    /// The number of bytes the relay has forwarded so far.
+    pub fn forwarded(&self) -> u64 {
+        self.state.forwarded.load(Ordering::Relaxed)
+    }
+}
+
+async fn serve_relay(mut stream: TcpStream, state: Arc<RelayState>) {
+    // Read the request byte by byte, so that nothing after it is read along with it.
+    let mut request = Vec::new();
+    while !request.ends_with(b"\n") {
+        let Ok(byte) = stream.read_u8().await else {
+            return;
+        };
+        request.push(byte);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let Some((token, side)) = request
+        .trim_end()
+        .strip_prefix("please relay ")
+        .and_then(|request| request.split_once(" for side "))
+    else {
+        return;
+    };
+
+    let mut peer = {
+        let mut waiting = state.waiting.lock().unwrap();
+        match waiting.remove(token) {
+            Some((peer_side, peer)) if peer_side != side => peer,
+            _ => {
+                waiting.insert(token.to_string(), (side.to_string(), stream));
+                return;
+            }
+        }
+    };
+
+    if stream.write_all(b"ok\n").await.is_err() || peer.write_all(b"ok\n").await.is_err() {
+        return;
+    }
+
+    let (stream_read, stream_write) = stream.into_split();
+    let (peer_read, peer_write) = peer.into_split();
+    let cut = CancellationToken::new();
+    tokio::join!(
+        forward(stream_read, peer_write, &state, &cut),
+        forward(peer_read, stream_write, &state, &cut),
+    );
+}
+
+/// Forwards everything from one side to the other, until either side is done or cut off.
+async fn forward(
+    mut from: OwnedReadHalf,
+    mut to: OwnedWriteHalf,
+    state: &RelayState,
+    cut: &CancellationToken,
+) {
+    let mut chunk = vec![0; 16 * 1024];
+    loop {
+        let len = tokio::select! {
+            read = from.read(&mut chunk) => read.unwrap_or(0),
+            () = cut.cancelled() => 0,
+        };
+        if len == 0 {
+            break;
+        }
+
+        let forwarded = state.forwarded.fetch_add(len as u64, Ordering::Relaxed) + len as u64;
+        if forwarded > state.limit.load(Ordering::Relaxed) {
+            break;
+        }
+        if to.write_all(&chunk[..len]).await.is_err() {
+            break;
+        }
+    }
+
+    // Dropping our halves along with the other direction's closes both connections.
+    cut.cancel();
+}
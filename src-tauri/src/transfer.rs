@@ -0,0 +1,1084 @@
+use crate::archive::{self, Bundle, Manifest, ARCHIVE_SUFFIX};
+use crate::collision::Plan;
+use crate::digest::{self, DigestHandle, HashingReader, HashingWriter};
+use crate::error::Error;
+use crate::filename;
+use crate::pylon::{Pylon, PylonBuilder, PylonError, ReceiveRequest};
+use crate::relay;
+use crate::resume::{self, Fingerprint, ResumeStore, Spool, HASH_PREFIX_LEN};
+use crate::settings::{self, Settings};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+use tokio_util::sync::CancellationToken;
+
+/// The capacity of the in-memory pipe that archives are streamed through.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// The name messages are offered under, to tell them apart from files.
+const MESSAGE_NAME: &str = "message.pylon-text";
+
+/// The maximum size of a message, in bytes. Messages are kept in memory, so anything larger
+/// should be sent as a file.
+pub const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+
+/// Builds a new Pylon, configured from the given settings.
+///
+/// # Arguments
+///
+/// * `id` - The ID to identify the Pylon with. Peers must use the same ID to connect.
+/// * `settings` - The settings to configure the Pylon with.
+pub fn build_pylon(id: String, settings: &Settings) -> Result<Pylon, Error> {
+    let mut builder = PylonBuilder::default();
+    builder.id(id);
+
+    if let Some(url) = &settings.rendezvous_url {
+        // The settings file may have been edited by hand, so don't take its word for it.
+        settings::validate_rendezvous_url(url)?;
+        builder.rendezvous_url(url.clone());
+    }
+
+    let relay_urls = relay::relay_urls(settings);
+    for url in &relay_urls {
+        settings::validate_relay_url(url)?;
+    }
+    builder.relay_urls(relay_urls);
+
+    Ok(builder.build()?)
+}
+
+/// Sent to the peer before every offer, so that they can see what it is before accepting or
+/// rejecting it, and tell if they already have part of it.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Preamble {
+    fingerprint: Fingerprint,
+    /// What an archive contains. `None` for anything else.
+    manifest: Option<Manifest>,
+}
+
+/// The peer's answer to a [`Preamble`].
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+enum Answer {
+    Accept {
+        /// The number of bytes the peer already has, which aren't sent again.
+        offset: u64,
+    },
+    Reject,
+}
+
+/// Announces an offer to the peer, and waits for them to accept or reject it.
+///
+/// Returns the number of bytes the peer already has, which aren't sent again.
+async fn announce(
+    pylon: &mut Pylon,
+    preamble: &Preamble,
+    cancel: &CancellationToken,
+) -> Result<u64, Error> {
+    until_cancelled(cancel, pylon.send_message(preamble)).await?;
+
+    match until_cancelled(cancel, pylon.receive_message()).await? {
+        Answer::Accept { offset } if offset > preamble.fingerprint.size => {
+            Err(invalid_data("the peer asked for more than was offered").into())
+        }
+        Answer::Accept { offset } => Ok(offset),
+        Answer::Reject => Err(Error::Rejected),
+    }
+}
+
+/// Accepts an announced offer, and waits for the peer to make it.
+///
+/// # Arguments
+///
+/// * `pylon` - The Pylon the offer was announced through.
+/// * `fingerprint` - The announced offer.
+/// * `offset` - The number of bytes already received, which the peer leaves out.
+/// * `cancel` - Cancels the transfer.
+async fn request(
+    pylon: &mut Pylon,
+    fingerprint: &Fingerprint,
+    offset: u64,
+    cancel: &CancellationToken,
+) -> Result<ReceiveRequest, Error> {
+    until_cancelled(cancel, pylon.send_message(&Answer::Accept { offset })).await?;
+
+    let request = pylon
+        .request_file(cancel.cancelled())
+        .await?
+        .ok_or(Error::Cancelled)?;
+    if request.file_name() != fingerprint.name || offset + request.file_size() != fingerprint.size {
+        return Err(invalid_data("the offer doesn't match its announcement").into());
+    }
+
+    Ok(request)
+}
+
+/// Runs a step of the transfer that involves the peer, unless it's cancelled first.
+async fn until_cancelled<T>(
+    cancel: &CancellationToken,
+    step: impl Future<Output = Result<T, PylonError>>,
+) -> Result<T, Error> {
+    tokio::select! {
+        result = step => Ok(result?),
+        () = cancel.cancelled() => Err(Error::Cancelled),
+    }
+}
+
+/// Files and folders, ready to be sent.
+pub struct Outgoing {
+    name: String,
+    bundle: Bundle,
+}
+
+impl Outgoing {
+    /// Prepares the files and folders at the given paths to be sent.
+    ///
+    /// Everything, even a single file, is bundled into an archive, along with a manifest
+    /// describing its contents and a digest to check it against.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The paths of the files and folders to send.
+    pub async fn new(paths: &[PathBuf]) -> Result<Self, Error> {
+        let name = match paths {
+            [] => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "nothing to send").into())
+            }
+            [path] => path
+                .file_name()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "path has no file name")
+                })?
+                .to_string_lossy()
+                .into_owned(),
+            _ => format!("{} files", paths.len()),
+        };
+
+        Ok(Self {
+            name: format!("{name}{ARCHIVE_SUFFIX}"),
+            bundle: Bundle::walk(paths).await?,
+        })
+    }
+
+    /// Describes what's being sent, the way the peer sees it.
+    pub fn offer(&self) -> Offer {
+        Offer::from_manifest(self.bundle.contents(), 0)
+    }
+}
+
+/// Sends files and folders to the peer.
+///
+/// The archive is streamed, without being written to disk first. If the peer already has the
+/// start of it, from an earlier transfer that was interrupted, only the rest is sent.
+///
+/// Returns the hex-encoded SHA-256 digest of what was sent.
+///
+/// # Arguments
+///
+/// * `pylon` - The Pylon to send with.
+/// * `outgoing` - The files and folders to send.
+/// * `progress_handler` - Called with the number of bytes sent so far, and the total.
+/// * `cancel` - Cancels the transfer.
+pub async fn send<P>(
+    pylon: &mut Pylon,
+    outgoing: Outgoing,
+    mut progress_handler: P,
+    cancel: &CancellationToken,
+) -> Result<String, Error>
+where
+    P: FnMut(u64, u64) + Send + 'static,
+{
+    let Outgoing { name, bundle } = outgoing;
+    let size = bundle.size();
+    tracing::info!(size, "sending archive");
+
+    let fingerprint = Fingerprint {
+        name: name.clone(),
+        size,
+        hash_prefix: hash_prefix(&bundle).await?,
+    };
+    let preamble = Preamble {
+        fingerprint,
+        manifest: Some(bundle.contents().clone()),
+    };
+    let offset = announce(pylon, &preamble, cancel).await?;
+
+    let (reader, writer) = tokio::io::duplex(PIPE_CAPACITY);
+    let (sent, packed) = tokio::join!(
+        async move {
+            // Dropping the reader once we're done unblocks the writer, should we stop reading
+            // early.
+            let mut reader = reader;
+            tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink()).await?;
+
+            pylon
+                .send_file(
+                    &mut reader.compat(),
+                    name,
+                    size - offset,
+                    move |done, total| progress_handler(offset + done, offset + total),
+                    cancel.cancelled(),
+                )
+                .await?;
+
+            Ok::<_, Error>(())
+        },
+        bundle.write_to(writer),
+    );
+
+    // A broken pipe only means that sending stopped early, so report why it did.
+    match packed {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err.into()),
+        packed => {
+            sent?;
+            Ok(packed?)
+        }
+    }
+}
+
+/// Hashes the first [`HASH_PREFIX_LEN`] bytes of the archive, to fingerprint it with.
+async fn hash_prefix(bundle: &Bundle) -> io::Result<String> {
+    let (reader, writer) = tokio::io::duplex(PIPE_CAPACITY);
+    let (hashed, packed) = tokio::join!(
+        async move {
+            let mut hasher = HashingWriter::new(tokio::io::sink());
+            tokio::io::copy(&mut reader.take(HASH_PREFIX_LEN), &mut hasher).await?;
+
+            Ok(hasher.finish().1)
+        },
+        bundle.write_to(writer),
+    );
+
+    // Packing stops early once the prefix has been hashed.
+    match packed {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err),
+        _ => hashed,
+    }
+}
+
+/// Sends a short message to the peer.
+///
+/// Returns the hex-encoded SHA-256 digest of the message.
+///
+/// # Arguments
+///
+/// * `pylon` - The Pylon to send with.
+/// * `text` - The message to send.
+/// * `progress_handler` - Called with the number of bytes sent so far, and the total.
+/// * `cancel` - Cancels the transfer.
+pub async fn send_text<P>(
+    pylon: &mut Pylon,
+    text: String,
+    progress_handler: P,
+    cancel: &CancellationToken,
+) -> Result<String, Error>
+where
+    P: FnMut(u64, u64) + Send + 'static,
+{
+    let size = text.len() as u64;
+    if text.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "nothing to send").into());
+    }
+    if size > MAX_MESSAGE_SIZE {
+        return Err(Error::TextTooLong {
+            size,
+            max: MAX_MESSAGE_SIZE,
+        });
+    }
+
+    // Messages are shorter than the hashed prefix, so their fingerprint holds their digest.
+    let digest = digest::digest(text.as_bytes());
+    let fingerprint = Fingerprint {
+        name: MESSAGE_NAME.to_string(),
+        size,
+        hash_prefix: digest.clone(),
+    };
+    let preamble = Preamble {
+        fingerprint,
+        manifest: None,
+    };
+    announce(pylon, &preamble, cancel).await?;
+
+    let mut reader = futures::io::Cursor::new(text.into_bytes());
+    pylon
+        .send_file(
+            &mut reader,
+            MESSAGE_NAME.to_string(),
+            size,
+            progress_handler,
+            cancel.cancelled(),
+        )
+        .await?;
+
+    Ok(digest)
+}
+
+/// Receives an announced message into memory.
+///
+/// Fails with [`Error::IntegrityMismatch`] if it doesn't match the digest it was announced with.
+async fn receive_text(
+    mut pylon: Pylon,
+    fingerprint: Fingerprint,
+    progress_handler: ProgressHandler,
+    cancel: &CancellationToken,
+) -> Result<Message, Error> {
+    let size = fingerprint.size;
+    if size > MAX_MESSAGE_SIZE {
+        until_cancelled(cancel, pylon.send_message(&Answer::Reject)).await?;
+        return Err(Error::TextTooLong {
+            size,
+            max: MAX_MESSAGE_SIZE,
+        });
+    }
+
+    let request = request(&mut pylon, &fingerprint, 0, cancel).await?;
+    let mut data = Vec::with_capacity(size as usize);
+    request
+        .accept(&mut data, progress_handler, cancel.cancelled())
+        .await?;
+    if cancel.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+
+    let digest = digest::digest(&data);
+    if digest != fingerprint.hash_prefix {
+        return Err(Error::IntegrityMismatch {
+            expected: fingerprint.hash_prefix,
+            actual: digest,
+        });
+    }
+    let text = String::from_utf8(data)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the message isn't text"))?;
+
+    Ok(Message { text, digest })
+}
+
+/// Checks that the given directory's volume has enough free space to receive an offer.
+///
+/// # Arguments
+///
+/// * `destination_dir` - The directory the offer will be saved in.
+/// * `required` - The number of bytes the offer needs.
+pub async fn check_free_space(destination_dir: &Path, required: u64) -> Result<(), Error> {
+    let available = tokio::task::spawn_blocking({
+        let destination_dir = destination_dir.to_path_buf();
+        move || fs4::available_space(destination_dir)
+    })
+    .await
+    .map_err(io::Error::other)??;
+
+    if available < required {
+        return Err(Error::InsufficientSpace {
+            required,
+            available,
+        });
+    }
+
+    Ok(())
+}
+
+/// Receives a single file into the given path.
+///
+/// Returns the hex-encoded SHA-256 digest of the file.
+async fn receive_file(
+    request: ReceiveRequest,
+    path: &Path,
+    progress_handler: ProgressHandler,
+    cancel: &CancellationToken,
+) -> Result<String, Error> {
+    let mut file = HashingWriter::new(fs::File::create(path).await?);
+    request
+        .accept(
+            &mut (&mut file).compat_write(),
+            progress_handler,
+            cancel.cancelled(),
+        )
+        .await?;
+    file.flush().await?;
+
+    Ok(file.finish().1)
+}
+
+/// Receives an archive, spooling it as it comes in, and unpacks it where the given plan says.
+///
+/// Returns the hex-encoded SHA-256 digest the archive ends with, once checked. The spool is only
+/// kept if the transfer was cut short, so that it can be resumed.
+///
+/// # Arguments
+///
+/// * `pylon` - The Pylon the archive was announced through.
+/// * `announced` - The archive's fingerprint and manifest, as announced by the peer.
+/// * `spool` - Where to spool the archive.
+/// * `plan` - Where to save each of the archive's top-level files and folders.
+/// * `progress_handler` - Called with the number of bytes received so far, and the total.
+/// * `cancel` - Cancels the transfer.
+async fn receive_archive(
+    pylon: &mut Pylon,
+    (fingerprint, announced): (&Fingerprint, &Manifest),
+    spool: Spool,
+    plan: &Plan,
+    mut progress_handler: ProgressHandler,
+    cancel: &CancellationToken,
+) -> Result<String, Error> {
+    let offset = spool.offset();
+    let request = request(pylon, fingerprint, offset, cancel).await?;
+
+    let (reader, writer) = tokio::io::duplex(PIPE_CAPACITY);
+    let reader = spool.read_earlier().await?.chain(reader);
+    let (mut reader, digest) = HashingReader::new(
+        reader,
+        fingerprint.size.saturating_sub(archive::TRAILER_SIZE),
+    );
+    let mut appended = spool.append().await?;
+
+    // Received bytes are spooled before being unpacked. Dropping the writers once we're done
+    // signals the end of the archive, and should unpacking fail, dropping the reader stops the
+    // transfer.
+    let (received, received_writer) = tokio::io::duplex(PIPE_CAPACITY);
+    let targets = plan.part_paths();
+    let (accepted, spooled, unpacked) = tokio::join!(
+        async move {
+            request
+                .accept(
+                    &mut received_writer.compat_write(),
+                    move |done, total| progress_handler(offset + done, offset + total),
+                    cancel.cancelled(),
+                )
+                .await
+        },
+        resume::spool(received, &mut appended, writer),
+        async move {
+            if archive::read_manifest(&mut reader).await? != *announced {
+                return Err(invalid_data("the archive doesn't match its announcement"));
+            }
+
+            archive::unpack(reader, plan.destination_dir(), &targets).await
+        },
+    );
+
+    // A broken pipe only means that unpacking stopped early, which it reports itself.
+    let accepted = match spooled {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err.into()),
+        _ => accepted.map_err(Error::from),
+    };
+
+    // Only an archive that was cut short is worth resuming.
+    let cut_short = cancel.is_cancelled() || accepted.is_err();
+
+    // If receiving failed, the archive was cut short, so report why it was.
+    let received = match unpacked {
+        Err(err) if err.kind() != io::ErrorKind::UnexpectedEof => Err(err.into()),
+        unpacked => accepted
+            .and_then(|()| Ok(unpacked?))
+            .and_then(|expected| verify(expected, &digest)),
+    };
+
+    if received.is_ok() || !cut_short {
+        spool.remove().await;
+    }
+
+    received
+}
+
+/// Checks the digest sent by the peer against the one computed while receiving.
+fn verify(expected: String, digest: &DigestHandle) -> Result<String, Error> {
+    match digest.get() {
+        Some(actual) if actual == expected => Ok(expected),
+        actual => Err(Error::IntegrityMismatch {
+            expected,
+            actual: actual.unwrap_or_default().to_string(),
+        }),
+    }
+}
+
+/// Called with the number of bytes transferred so far, and the total.
+pub type ProgressHandler = Box<dyn FnMut(u64, u64) + Send + 'static>;
+
+/// A description of what the peer is offering.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Offer {
+    /// The names of the offered top-level files and folders.
+    pub names: Vec<String>,
+    /// The total size of the offered files.
+    pub size: u64,
+    /// The number of offered files.
+    pub entry_count: u64,
+    /// The number of bytes already received, in an earlier transfer that was interrupted.
+    pub resumed_bytes: u64,
+}
+
+impl Offer {
+    /// Describes the contents of an offered archive.
+    fn from_manifest(manifest: &Manifest, resumed_bytes: u64) -> Self {
+        Self {
+            names: manifest.entries.iter().map(|e| e.name.clone()).collect(),
+            size: manifest.entries.iter().map(|e| e.size).sum(),
+            entry_count: manifest.entries.iter().map(|e| e.file_count).sum(),
+            resumed_bytes,
+        }
+    }
+}
+
+/// A short message received from the peer.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub text: String,
+    /// The hex-encoded SHA-256 digest of the message.
+    pub digest: String,
+}
+
+/// What the peer sent, once connected.
+pub enum Connected {
+    /// Files and folders, waiting to be accepted or rejected.
+    Offer(Box<Incoming>),
+    /// A message, which is received straight away, as it's never saved anywhere.
+    Message(Message),
+}
+
+/// What was saved after accepting an offer.
+pub struct Received {
+    pub paths: Vec<PathBuf>,
+    /// The hex-encoded SHA-256 digest of what was received. `None` if everything was skipped.
+    pub digest: Option<String>,
+    /// Whether the digest was checked against the peer's. Only Pylon archives come with one.
+    pub verified: bool,
+}
+
+/// How an offer will be received, once accepted.
+enum Pending {
+    File,
+    Archive {
+        /// What the archive contains, as announced by the peer.
+        manifest: Manifest,
+        spool: Spool,
+    },
+}
+
+/// An offer from the peer, waiting to be accepted or rejected.
+///
+/// Nothing has been sent yet, and the peer is waiting on the answer.
+pub struct Incoming {
+    /// The Pylon the offer was announced through.
+    pylon: Pylon,
+    fingerprint: Fingerprint,
+    offer: Offer,
+    pending: Pending,
+    progress_handler: ProgressHandler,
+}
+
+impl Incoming {
+    /// Connects to the peer that generated the given code, and waits for their offer.
+    ///
+    /// Messages are received in memory right away, rather than offered. Archives are spooled in
+    /// the resume store once they're accepted. If part of the same archive was received before,
+    /// only the rest of it is requested from the peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `pylon` - The Pylon to receive with.
+    /// * `code` - The Pylon code to connect with.
+    /// * `store` - Where to keep partially received archives.
+    /// * `progress_handler` - Called with the number of bytes received so far, and the total.
+    /// * `cancel` - Cancels the transfer.
+    pub async fn connect(
+        mut pylon: Pylon,
+        code: String,
+        store: &ResumeStore,
+        progress_handler: ProgressHandler,
+        cancel: &CancellationToken,
+    ) -> Result<Connected, Error> {
+        until_cancelled(cancel, pylon.connect(code)).await?;
+        let Preamble {
+            fingerprint,
+            manifest,
+        } = until_cancelled(cancel, pylon.receive_message()).await?;
+
+        if fingerprint.name == MESSAGE_NAME {
+            return receive_text(pylon, fingerprint, progress_handler, cancel)
+                .await
+                .map(Connected::Message);
+        }
+
+        // Only archives are ever resumed.
+        let (offer, pending) = if fingerprint.name.ends_with(ARCHIVE_SUFFIX) {
+            let mut manifest =
+                manifest.ok_or_else(|| invalid_data("the archive's contents weren't announced"))?;
+            manifest.validate()?;
+            let spool = store.open(&fingerprint).await?;
+            if spool.offset() > 0 {
+                tracing::info!(offset = spool.offset(), "resuming archive");
+            }
+
+            let offer = Offer::from_manifest(&manifest, spool.offset());
+            (offer, Pending::Archive { manifest, spool })
+        } else {
+            let offer = Offer {
+                names: vec![filename::sanitize(&fingerprint.name)],
+                size: fingerprint.size,
+                entry_count: 1,
+                resumed_bytes: 0,
+            };
+            (offer, Pending::File)
+        };
+
+        Ok(Connected::Offer(Box::new(Self {
+            pylon,
+            fingerprint,
+            offer,
+            pending,
+            progress_handler,
+        })))
+    }
+
+    /// Describes what the peer is offering.
+    pub fn offer(&self) -> &Offer {
+        &self.offer
+    }
+
+    /// Accepts the offer, saving everything where the given plan says.
+    ///
+    /// Everything is received into hidden part folders first, and only moved into place once
+    /// the transfer has completed. The part folders are removed should it fail.
+    ///
+    /// Archives are checked against the digest they end with, and fail to be received if they
+    /// don't match it. Their spool is only kept if the transfer was cut short, so that it can be
+    /// resumed.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - Where to save each of the offered files and folders.
+    /// * `cancel` - Cancels the transfer.
+    pub async fn accept(self, plan: Plan, cancel: &CancellationToken) -> Result<Received, Error> {
+        if plan.skips_all() {
+            self.reject().await?;
+            return Ok(Received {
+                paths: Vec::new(),
+                digest: None,
+                verified: false,
+            });
+        }
+
+        let Self {
+            mut pylon,
+            fingerprint,
+            offer,
+            pending,
+            progress_handler,
+        } = self;
+        let received = match pending {
+            Pending::File => {
+                let part = plan
+                    .part_path(&offer.names[0])
+                    .expect("a single file is only skipped along with everything else");
+
+                match request(&mut pylon, &fingerprint, 0, cancel).await {
+                    Ok(request) => receive_file(request, part, progress_handler, cancel)
+                        .await
+                        .map(|digest| (digest, false)),
+                    Err(err) => Err(err),
+                }
+            }
+            Pending::Archive { manifest, spool } => {
+                let announced = (&fingerprint, &manifest);
+                receive_archive(
+                    &mut pylon,
+                    announced,
+                    spool,
+                    &plan,
+                    progress_handler,
+                    cancel,
+                )
+                .await
+                .map(|digest| (digest, true))
+            }
+        };
+
+        match received {
+            Ok((digest, verified)) => Ok(Received {
+                paths: plan.commit().await?,
+                digest: Some(digest),
+                verified,
+            }),
+            Err(err) => {
+                plan.discard().await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Rejects the offer, letting the peer know.
+    pub async fn reject(mut self) -> Result<(), Error> {
+        let answered = self.pylon.send_message(&Answer::Reject).await;
+        if let Pending::Archive { spool, .. } = self.pending {
+            spool.remove().await;
+        }
+
+        Ok(answered?)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::CollisionPolicy;
+    use crate::testing::{self, Relay};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    const APP_ID: &str = "com.pylon.test";
+
+    /// The size of the file sent in each test, large enough for a transfer to be cut off midway.
+    const FILE_SIZE: usize = 3 * 1024 * 1024;
+
+    struct Setup {
+        dir: TempDir,
+        rendezvous_url: String,
+        relay: Relay,
+        store: ResumeStore,
+    }
+
+    impl Setup {
+        async fn new() -> Self {
+            let dir = TempDir::new().unwrap();
+            let store = ResumeStore::new(dir.path().join("partial"));
+            fs::create_dir_all(dir.path().join("sent/folder"))
+                .await
+                .unwrap();
+            fs::create_dir(dir.path().join("received")).await.unwrap();
+
+            Self {
+                dir,
+                rendezvous_url: testing::rendezvous_server().await,
+                relay: Relay::start().await,
+                store,
+            }
+        }
+
+        fn path(&self, path: &str) -> PathBuf {
+            self.dir.path().join(path)
+        }
+
+        fn pylon(&self) -> Pylon {
+            PylonBuilder::default()
+                .id(APP_ID.to_string())
+                .rendezvous_url(self.rendezvous_url.clone())
+                .relay_urls(vec![self.relay.url.clone()])
+                .force_relay()
+                .build()
+                .unwrap()
+        }
+
+        /// Sends the folder to a new receiver, which accepts it.
+        async fn transfer(&self) -> (Result<String, Error>, Result<(Offer, Received), Error>) {
+            self.transfer_of("sent/folder").await
+        }
+
+        /// Sends the given file or folder to a new receiver, which accepts it.
+        async fn transfer_of(
+            &self,
+            path: &str,
+        ) -> (Result<String, Error>, Result<(Offer, Received), Error>) {
+            let mut sender = self.pylon();
+            let code = sender.gen_code(2).await.unwrap();
+            let outgoing = Outgoing::new(&[self.path(path)]).await.unwrap();
+            let cancel = CancellationToken::new();
+
+            let received = async {
+                let connected = Incoming::connect(
+                    self.pylon(),
+                    code,
+                    &self.store,
+                    Box::new(|_, _| {}),
+                    &cancel,
+                )
+                .await?;
+                let Connected::Offer(incoming) = connected else {
+                    panic!("expected an offer");
+                };
+                let offer = incoming.offer().clone();
+                let plan = Plan::new(
+                    &self.path("received"),
+                    &offer.names,
+                    CollisionPolicy::Ask,
+                    1,
+                )
+                .await?;
+
+                Ok((offer, incoming.accept(plan, &cancel).await?))
+            };
+
+            // Both sides together are too large a future for the test's stack.
+            let transferred = Box::pin(async {
+                tokio::join!(send(&mut sender, outgoing, |_, _| {}, &cancel), received)
+            });
+            tokio::time::timeout(Duration::from_secs(60), transferred)
+                .await
+                .expect("the transfer never finished")
+        }
+
+        /// Receives a message with the given code.
+        async fn receive_message(
+            &self,
+            code: String,
+            cancel: &CancellationToken,
+        ) -> Result<Message, Error> {
+            let connected =
+                Incoming::connect(self.pylon(), code, &self.store, Box::new(|_, _| {}), cancel)
+                    .await?;
+            let Connected::Message(message) = connected else {
+                panic!("expected a message");
+            };
+
+            Ok(message)
+        }
+
+        /// Lists what's in the given directory.
+        async fn list(&self, path: &str) -> Vec<String> {
+            let mut names = Vec::new();
+            let mut dir = fs::read_dir(self.path(path)).await.unwrap();
+            while let Some(entry) = dir.next_entry().await.unwrap() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+
+            names
+        }
+    }
+
+    fn contents(seed: u8) -> Vec<u8> {
+        (0..FILE_SIZE).map(|i| (i % 251) as u8 ^ seed).collect()
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_transfer_is_resumed() {
+        let setup = Setup::new().await;
+        let data = contents(0);
+        fs::write(setup.path("sent/folder/data.bin"), &data)
+            .await
+            .unwrap();
+
+        setup.relay.cut_off_after(FILE_SIZE as u64 / 2);
+        let (sent, received) = setup.transfer().await;
+        assert!(sent.is_err());
+        assert!(received.is_err());
+        assert!(setup.list("received").await.is_empty());
+        assert_eq!(setup.list("partial").await.len(), 2);
+
+        setup.relay.cut_off_after(u64::MAX);
+        let forwarded = setup.relay.forwarded();
+        let (sent, received) = setup.transfer().await;
+        let digest = sent.unwrap();
+        let (offer, received) = received.unwrap();
+
+        assert!(offer.resumed_bytes > 0);
+        assert!(offer.resumed_bytes < FILE_SIZE as u64);
+        assert_eq!(received.digest, Some(digest));
+        assert!(received.verified);
+        let saved = fs::read(setup.path("received/folder/data.bin")).await;
+        assert!(saved.unwrap() == data);
+
+        // Only the rest was sent again, and nothing is kept once it's been received.
+        assert!(setup.relay.forwarded() - forwarded < FILE_SIZE as u64 - offer.resumed_bytes / 2);
+        assert!(setup.list("partial").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_offer_is_described_and_can_be_rejected_before_anything_is_sent() {
+        let setup = Setup::new().await;
+        fs::write(setup.path("sent/folder/data.bin"), contents(0))
+            .await
+            .unwrap();
+        fs::write(setup.path("sent/folder/notes.txt"), "notes")
+            .await
+            .unwrap();
+
+        let mut sender = setup.pylon();
+        let code = sender.gen_code(2).await.unwrap();
+        let outgoing = Outgoing::new(&[setup.path("sent/folder")]).await.unwrap();
+        let cancel = CancellationToken::new();
+
+        let received = async {
+            let connected = Incoming::connect(
+                setup.pylon(),
+                code,
+                &setup.store,
+                Box::new(|_, _| {}),
+                &cancel,
+            )
+            .await?;
+            let Connected::Offer(incoming) = connected else {
+                panic!("expected an offer");
+            };
+            let offer = incoming.offer().clone();
+            incoming.reject().await?;
+
+            Ok::<_, Error>(offer)
+        };
+        let (sent, offer) = tokio::join!(send(&mut sender, outgoing, |_, _| {}, &cancel), received);
+
+        let offer = offer.unwrap();
+        assert_eq!(offer.names, ["folder"]);
+        assert_eq!(offer.size, FILE_SIZE as u64 + 5);
+        assert_eq!(offer.entry_count, 2);
+        assert!(matches!(sent, Err(Error::Rejected)));
+        assert_eq!(setup.relay.forwarded(), 0);
+        assert!(setup.list("partial").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn messages_are_checked_against_their_digest() {
+        let setup = Setup::new().await;
+        let cancel = CancellationToken::new();
+
+        let mut sender = setup.pylon();
+        let code = sender.gen_code(2).await.unwrap();
+        let (sent, received) = tokio::join!(
+            send_text(&mut sender, "hello".to_string(), |_, _| {}, &cancel),
+            setup.receive_message(code, &cancel),
+        );
+        let message = received.unwrap();
+        assert_eq!(message.text, "hello");
+        assert_eq!(message.digest, sent.unwrap());
+
+        // A message that was changed on the way no longer matches its announced digest.
+        let mut sender = setup.pylon();
+        let code = sender.gen_code(2).await.unwrap();
+        let tampered = async {
+            let preamble = Preamble {
+                fingerprint: Fingerprint {
+                    name: MESSAGE_NAME.to_string(),
+                    size: 5,
+                    hash_prefix: digest::digest(b"hello"),
+                },
+                manifest: None,
+            };
+            announce(&mut sender, &preamble, &cancel).await?;
+
+            let mut reader = futures::io::Cursor::new(b"jello".to_vec());
+            let name = MESSAGE_NAME.to_string();
+            sender
+                .send_file(&mut reader, name, 5, |_, _| {}, cancel.cancelled())
+                .await?;
+            Ok::<_, Error>(())
+        };
+        let (_, received) = tokio::join!(tampered, setup.receive_message(code, &cancel));
+        assert!(matches!(received, Err(Error::IntegrityMismatch { .. })));
+    }
+
+    #[tokio::test]
+    async fn a_changed_offer_starts_over() {
+        let setup = Setup::new().await;
+        fs::write(setup.path("sent/folder/data.bin"), contents(0))
+            .await
+            .unwrap();
+
+        setup.relay.cut_off_after(FILE_SIZE as u64 / 2);
+        let (_, received) = setup.transfer().await;
+        assert!(received.is_err());
+
+        let data = contents(1);
+        fs::write(setup.path("sent/folder/data.bin"), &data)
+            .await
+            .unwrap();
+        setup.relay.cut_off_after(u64::MAX);
+        let (sent, received) = setup.transfer().await;
+        let (offer, received) = received.unwrap();
+
+        assert_eq!(offer.resumed_bytes, 0);
+        assert_eq!(received.digest, Some(sent.unwrap()));
+        let saved = fs::read(setup.path("received/folder/data.bin")).await;
+        assert!(saved.unwrap() == data);
+    }
+
+    #[tokio::test]
+    async fn a_corrupted_spool_is_dropped() {
+        let setup = Setup::new().await;
+        let data = contents(0);
+        fs::write(setup.path("sent/folder/data.bin"), &data)
+            .await
+            .unwrap();
+
+        setup.relay.cut_off_after(FILE_SIZE as u64 / 2);
+        let (_, received) = setup.transfer().await;
+        assert!(received.is_err());
+
+        // Corrupt what was received so far, past the part that identifies the offer.
+        for name in setup.list("partial").await {
+            if name.ends_with(".part") {
+                let path = setup.path("partial").join(name);
+                let mut spooled = fs::read(&path).await.unwrap();
+                let len = spooled.len();
+                spooled[len - 1] ^= 0xff;
+                fs::write(&path, spooled).await.unwrap();
+            }
+        }
+
+        setup.relay.cut_off_after(u64::MAX);
+        let (_, received) = setup.transfer().await;
+        assert!(matches!(received, Err(Error::IntegrityMismatch { .. })));
+        assert!(setup.list("received").await.is_empty());
+        assert!(setup.list("partial").await.is_empty());
+
+        let (_, received) = setup.transfer().await;
+        let (offer, _) = received.unwrap();
+        assert_eq!(offer.resumed_bytes, 0);
+        let saved = fs::read(setup.path("received/folder/data.bin")).await;
+        assert!(saved.unwrap() == data);
+    }
+
+    #[tokio::test]
+    async fn names_of_the_longest_length_are_received() {
+        let setup = Setup::new().await;
+        let name = format!("{}.txt", "a".repeat(251));
+        assert_eq!(name.len(), 255);
+        fs::write(setup.path("sent").join(&name), contents(0))
+            .await
+            .unwrap();
+
+        let (_, received) = setup.transfer_of(&format!("sent/{name}")).await;
+        let (offer, received) = received.unwrap();
+
+        let saved = &received.paths[0];
+        assert_eq!(offer.names, [filename::sanitize(&name)]);
+        assert!(saved
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .ends_with(".txt"));
+        assert!(fs::read(saved).await.unwrap() == contents(0));
+    }
+
+    #[tokio::test]
+    async fn free_space_is_enough_for_nothing() {
+        check_free_space(&std::env::temp_dir(), 0).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn free_space_is_never_enough_for_everything() {
+        let result = check_free_space(&std::env::temp_dir(), u64::MAX).await;
+        assert!(matches!(
+            result,
+            Err(Error::InsufficientSpace {
+                required: u64::MAX,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn free_space_of_a_missing_directory_is_an_io_error() {
+        let dir = TempDir::new().unwrap();
+        let result = check_free_space(&dir.path().join("missing/nested"), 0).await;
+        assert!(matches!(result, Err(Error::Io(_))), "{result:?}");
+    }
+}
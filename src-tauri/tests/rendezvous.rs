@@ -0,0 +1,54 @@
+use pylon_desktop::error::Error;
+use pylon_desktop::settings::Settings;
+use pylon_desktop::transfer;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+const APP_ID: &str = "com.pylon.test";
+
+#[tokio::test]
+async fn pylon_connects_to_the_configured_rendezvous_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let settings = Settings {
+        rendezvous_url: Some(format!("ws://127.0.0.1:{port}/v1")),
+        use_default_relay: false,
+        ..Settings::default()
+    };
+
+    let mut pylon = transfer::build_pylon(APP_ID.to_string(), &settings).unwrap();
+    let accepted = async {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 1024];
+        let len = stream.read(&mut request).await.unwrap();
+        String::from_utf8_lossy(&request[..len]).into_owned()
+    };
+
+    // Our listener never completes the handshake, so generating the code can only fail.
+    let request = tokio::time::timeout(Duration::from_secs(10), async {
+        tokio::select! {
+            request = accepted => request,
+            result = pylon.gen_code(2) => panic!("expected to wait, got {:?}", result.err()),
+        }
+    })
+    .await
+    .expect("the Pylon never connected to the rendezvous server");
+
+    assert!(request.starts_with("GET /v1 HTTP/1.1\r\n"), "{request}");
+    assert!(
+        request.to_ascii_lowercase().contains("upgrade: websocket"),
+        "{request}"
+    );
+}
+
+#[test]
+fn pylon_refuses_an_invalid_rendezvous_server() {
+    let settings = Settings {
+        rendezvous_url: Some("http://127.0.0.1:4000/v1".to_string()),
+        ..Settings::default()
+    };
+
+    let result = transfer::build_pylon(APP_ID.to_string(), &settings);
+    assert!(matches!(result, Err(Error::InvalidServerUrl { .. })));
+}
@@ -1,17 +1,46 @@
 import { Tabs, Tab } from "@nextui-org/react";
 import Send from "./views/Send";
 import Receive from "./views/Receive";
+import History from "./views/History";
 import Settings, { Theme, ThemeChoice, Lang } from "./components/Settings";
-import { TbDownload, TbUpload } from "react-icons/tb";
+import { TbDownload, TbHistory, TbUpload } from "react-icons/tb";
 import { useEffect, useState } from "react";
 import { useTranslation } from "react-i18next";
 import * as bindings from "./bindings";
 
+// TODO: watch for changes to system theme.
+function detectSystemTheme(): Theme {
+  if (
+    window.matchMedia &&
+    window.matchMedia("(prefers-color-scheme: dark)").matches
+  ) {
+    return "dark";
+  } else {
+    return "light";
+  }
+}
+
+const persistSettings = function (changes: Partial<bindings.Settings>) {
+  bindings.updateSettings(changes).catch((err: Error) => {
+    console.error(err);
+  });
+};
+
 function App() {
   const { t, i18n } = useTranslation();
-  const [theme, setTheme] = useState<Theme>("light");
+  const [theme, setTheme] = useState<Theme>(detectSystemTheme());
   const [themeChoice, setThemeChoice] = useState<ThemeChoice>("system");
   const [lang, setLang] = useState<Lang>("en");
+  const [codeLength, setCodeLength] = useState<number>(2);
+  const [collisionPolicy, setCollisionPolicy] =
+    useState<bindings.CollisionPolicy>("rename");
+  const [downloadDir, setDownloadDir] = useState<string | null>(null);
+  const [rendezvousUrl, setRendezvousUrl] = useState<string | null>(null);
+  const [relayUrls, setRelayUrls] = useState<string[]>([]);
+  const [useDefaultRelay, setUseDefaultRelay] = useState<boolean>(true);
+  const [logLevel, setLogLevel] = useState<bindings.LogLevel>("info");
+  const [tab, setTab] = useState<string>("send");
+  const [link, setLink] = useState<bindings.ReceiveLink | null>(null);
 
   // Bootstrap stuff for when our app launches.
   useEffect(() => {
@@ -35,16 +64,56 @@ function App() {
         console.error(err);
       });
 
-    // Set initial language.
-    // TODO: set initial language based on detected locale, rather than force-setting English.
-    i18n.changeLanguage(lang).catch((err: Error) => {
-      console.error(err);
-    });
+    // Restore the persisted settings.
+    // TODO: set initial language based on detected locale, rather than defaulting to English.
+    bindings
+      .getSettings()
+      .then((settings: bindings.Settings) => {
+        i18n.changeLanguage(settings.language).catch((err: Error) => {
+          console.error(err);
+        });
+        setLang(settings.language as Lang);
 
-    // Set initial theme.
-    if (themeChoice === "system") {
-      setTheme(detectSystemTheme());
-    }
+        setThemeChoice(settings.theme);
+        setTheme(
+          settings.theme === "system" ? detectSystemTheme() : settings.theme
+        );
+
+        setCodeLength(settings.codeLength);
+        setCollisionPolicy(settings.collisionPolicy);
+        setDownloadDir(settings.downloadDir);
+        setRendezvousUrl(settings.rendezvousUrl);
+        setRelayUrls(settings.relayUrls);
+        setUseDefaultRelay(settings.useDefaultRelay);
+        setLogLevel(settings.logLevel);
+      })
+      .catch((err: Error) => {
+        console.error(err);
+      });
+  }, []);
+
+  // Open the Receive tab with the code of any link the app is opened with.
+  useEffect(() => {
+    const takeLink = () => {
+      bindings
+        .takePendingLink()
+        .then((link: bindings.ReceiveLink | null) => {
+          if (link !== null) {
+            setLink(link);
+            setTab("receive");
+          }
+        })
+        .catch((err: Error) => {
+          console.error(err);
+        });
+    };
+
+    takeLink();
+    const unlisten = bindings.onLink(takeLink);
+
+    return () => {
+      unlisten.then((stop) => stop());
+    };
   }, []);
 
   // Handle theme changes.
@@ -59,18 +128,6 @@ function App() {
     };
   }, [theme]);
 
-  // TODO: watch for changes to system theme.
-  const detectSystemTheme = function (): Theme {
-    if (
-      window.matchMedia &&
-      window.matchMedia("(prefers-color-scheme: dark)").matches
-    ) {
-      return "dark";
-    } else {
-      return "light";
-    }
-  };
-
   const onThemeChange = function (theme: React.ChangeEvent<HTMLSelectElement>) {
     if (theme.target.value == "system") {
       setTheme(detectSystemTheme());
@@ -78,12 +135,56 @@ function App() {
       setTheme(theme.target.value as Theme);
     }
 
-    setThemeChoice(theme.target.value as Theme);
+    setThemeChoice(theme.target.value as ThemeChoice);
+    persistSettings({ theme: theme.target.value as ThemeChoice });
   };
 
   const onLangChange = function (lang: React.ChangeEvent<HTMLSelectElement>) {
     i18n.changeLanguage(lang.target.value);
     setLang(lang.target.value as Lang);
+    persistSettings({ language: lang.target.value });
+  };
+
+  const onCodeLengthChange = function (
+    codeLength: React.ChangeEvent<HTMLSelectElement>
+  ) {
+    setCodeLength(Number(codeLength.target.value));
+    persistSettings({ codeLength: Number(codeLength.target.value) });
+  };
+
+  const onCollisionPolicyChange = function (
+    collisionPolicy: React.ChangeEvent<HTMLSelectElement>
+  ) {
+    const policy = collisionPolicy.target.value as bindings.CollisionPolicy;
+    setCollisionPolicy(policy);
+    persistSettings({ collisionPolicy: policy });
+  };
+
+  const onDownloadDirChange = async function (downloadDir: string | null) {
+    const settings = await bindings.updateSettings({ downloadDir });
+    setDownloadDir(settings.downloadDir);
+  };
+
+  // The URL is typed in, so let the settings show why it was rejected.
+  const onRendezvousUrlChange = async function (rendezvousUrl: string | null) {
+    const settings = await bindings.updateSettings({ rendezvousUrl });
+    setRendezvousUrl(settings.rendezvousUrl);
+  };
+
+  const onRelaysChange = async function (
+    changes: Pick<Partial<bindings.Settings>, "relayUrls" | "useDefaultRelay">
+  ) {
+    const settings = await bindings.updateSettings(changes);
+    setRelayUrls(settings.relayUrls);
+    setUseDefaultRelay(settings.useDefaultRelay);
+  };
+
+  const onLogLevelChange = function (
+    logLevel: React.ChangeEvent<HTMLSelectElement>
+  ) {
+    const level = logLevel.target.value as bindings.LogLevel;
+    setLogLevel(level);
+    persistSettings({ logLevel: level });
   };
 
   return (
@@ -94,6 +195,8 @@ function App() {
           color="primary"
           radius="md"
           aria-label={t("main.tabsAriaLabel")}
+          selectedKey={tab}
+          onSelectionChange={(key) => setTab(String(key))}
           classNames={{
             tabContent: "children:transition-none",
           }}
@@ -108,7 +211,7 @@ function App() {
               </div>
             }
           >
-            <Send />
+            <Send codeLength={codeLength} />
           </Tab>
 
           <Tab
@@ -121,7 +224,20 @@ function App() {
               </div>
             }
           >
-            <Receive />
+            <Receive link={link} />
+          </Tab>
+
+          <Tab
+            key="history"
+            className="w-full h-5/6"
+            title={
+              <div className="flex items-center space-x-2">
+                <TbHistory />
+                <span>{t("main.historyTabLabel")}</span>
+              </div>
+            }
+          >
+            <History />
           </Tab>
         </Tabs>
 
@@ -130,6 +246,19 @@ function App() {
           onThemeChange={onThemeChange}
           defaultLang={lang}
           onLangChange={onLangChange}
+          defaultCodeLength={codeLength}
+          onCodeLengthChange={onCodeLengthChange}
+          defaultCollisionPolicy={collisionPolicy}
+          onCollisionPolicyChange={onCollisionPolicyChange}
+          downloadDir={downloadDir}
+          onDownloadDirChange={onDownloadDirChange}
+          defaultRendezvousUrl={rendezvousUrl}
+          onRendezvousUrlChange={onRendezvousUrlChange}
+          relayUrls={relayUrls}
+          useDefaultRelay={useDefaultRelay}
+          onRelaysChange={onRelaysChange}
+          defaultLogLevel={logLevel}
+          onLogLevelChange={onLogLevelChange}
         />
       </div>
     </main>
@@ -1,4 +1,51 @@
 import { invoke } from "@tauri-apps/api";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
+import { TFunction } from "i18next";
+
+
+/**
+ * An error returned by a command.
+ *
+ * @export
+ * @interface CommandError
+ */
+export interface CommandError {
+	/** A stable identifier for the kind of error, used to look up its localized message. */
+	code: string;
+	/** An English description of the error, used when no localized message exists. */
+	message: string;
+	/** Values that give more context about the error, if any. */
+	details: Record<string, unknown> | null;
+}
+
+
+/**
+ * Indicates if the given value is an error returned by a command.
+ *
+ * @export
+ * @param {unknown} err The value to check.
+ * @returns {boolean} `true` if the value is a `CommandError`, `false` otherwise.
+ */
+export function isCommandError(err: unknown): err is CommandError {
+	return typeof err === "object" && err !== null && "code" in err && "message" in err;
+}
+
+
+/**
+ * Returns a localized message for the given error.
+ *
+ * @export
+ * @param {TFunction} t The translation function to use.
+ * @param {unknown} err The error to describe.
+ * @returns {string} The localized message.
+ */
+export function errorMessage(t: TFunction, err: unknown): string {
+	if (isCommandError(err)) {
+		return t(`errors.${err.code}`, { ...err.details, defaultValue: err.message });
+	}
+
+	return t("errors.unknown");
+}
 
 
 /**
@@ -14,13 +61,517 @@ export async function isReleaseMode(): Promise<boolean> {
 
 
 /**
- * Generates a Pylon code.
+ * The ID of a transfer session.
+ */
+export type SessionId = number;
+
+
+/**
+ * The lifecycle state of a transfer session.
+ */
+export type SessionState = "generating" | "waiting" | "transferring" | "done" | "failed" | "cancelled";
+
+
+/**
+ * A description of a transfer session.
+ *
+ * @export
+ * @interface SessionInfo
+ */
+export interface SessionInfo {
+	id: SessionId;
+	direction: "send" | "receive";
+	state: SessionState;
+	code: string | null;
+	paths: string[];
+	bytesTransferred: number;
+	error: string | null;
+}
+
+
+/**
+ * Creates a new send session, for `genCode` to generate its code.
+ *
+ * The session exists before its code does, so that it can be cancelled while it's generated.
  *
  * @export
  * @async
- * @param {number} codeLength The length of the code to generate.
+ * @returns {Promise<SessionId>} Resolves to the ID of the new session.
+ */
+export async function createSendSession(): Promise<SessionId> {
+	return await invoke("create_send_session");
+}
+
+
+/**
+ * Generates the Pylon code of the given send session.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session returned by `createSendSession`.
+ * @param {number} [codeLength] The length of the code to generate, between 2 and 8.
+ * Defaults to the code length from the settings.
  * @returns {Promise<string>} Resolves to the generated Pylon code.
  */
-export async function genCode(codeLength: number): Promise<string> {
-	return await invoke("gen_code", { codeLength });
+export async function genCode(id: SessionId, codeLength?: number): Promise<string> {
+	return await invoke("gen_code", { id, codeLength });
+}
+
+
+/**
+ * The maximum size of a message, in bytes. Keep in sync with `MAX_MESSAGE_SIZE` in the backend.
+ *
+ * @export
+ */
+export const MAX_MESSAGE_SIZE = 64 * 1024;
+
+
+/**
+ * Renders the code of the given session as a QR code.
+ *
+ * The QR code is rendered locally, without any external service.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session returned by `createSendSession`.
+ * @param {boolean} asUri Whether to wrap the code in a `pylon://receive` link, which opens the app.
+ * @returns {Promise<string>} Resolves to the QR code, as an SVG document.
+ */
+export async function codeQr(id: SessionId, asUri: boolean): Promise<string> {
+	return await invoke("code_qr", { id, asUri });
+}
+
+
+/**
+ * The result of a completed send.
+ *
+ * @export
+ * @interface SendResult
+ */
+export interface SendResult {
+	paths: string[];
+	bytesSent: number;
+	/** The hex-encoded SHA-256 digest of what was sent. */
+	digest: string;
+}
+
+
+/**
+ * Sends files and folders using the Pylon code of the given session.
+ *
+ * Everything is sent in a single transfer.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session returned by `createSendSession`.
+ * @param {string[]} paths The paths of the files and folders to send.
+ * @returns {Promise<SendResult>} Resolves once everything has been sent to the peer.
+ */
+export async function sendFile(id: SessionId, paths: string[]): Promise<SendResult> {
+	return await invoke("send_file", { id, paths });
+}
+
+
+/**
+ * Sends a short message using the Pylon code of the given session.
+ *
+ * The peer is shown the message, which is never saved on either side.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session returned by `createSendSession`.
+ * @param {string} text The message to send, at most `MAX_MESSAGE_SIZE` bytes long.
+ * @returns {Promise<SendResult>} Resolves once the message has been sent to the peer.
+ */
+export async function sendText(id: SessionId, text: string): Promise<SendResult> {
+	return await invoke("send_text", { id, text });
+}
+
+
+/**
+ * The result of a completed receive.
+ *
+ * @export
+ * @interface ReceiveResult
+ */
+export interface ReceiveResult {
+	paths: string[];
+	bytesReceived: number;
+	/** The hex-encoded SHA-256 digest of what was received. `null` if everything was skipped. */
+	digest: string | null;
+	/** Whether the digest matched the sender's. Only Pylon peers send one. */
+	verified: boolean;
+}
+
+
+/**
+ * What to do when a received file or folder has the same name as an existing one.
+ *
+ * `"ask"` makes `acceptOffer` fail with a `name_collision` error, listing the taken names, so
+ * that it can be called again with another policy.
+ *
+ * @export
+ */
+export type CollisionPolicy = "rename" | "overwrite" | "skip" | "ask";
+
+
+/**
+ * An offer from a peer, along with the session it belongs to.
+ *
+ * @export
+ * @interface PendingOffer
+ */
+export interface PendingOffer {
+	id: SessionId;
+	/** The names of the offered top-level files and folders. */
+	names: string[];
+	/** The total size of the offered files, in bytes. */
+	size: number;
+	/** The number of offered files. */
+	entryCount: number;
+	/** The number of bytes already received, in an earlier transfer that was interrupted. */
+	resumedBytes: number;
+}
+
+
+/**
+ * A message from a peer, along with the session it belongs to.
+ *
+ * @export
+ * @interface ReceivedMessage
+ */
+export interface ReceivedMessage {
+	id: SessionId;
+	text: string;
+	/** The hex-encoded SHA-256 digest of the message. */
+	digest: string;
+}
+
+
+/**
+ * What a peer sent, once connected: either an offer of files and folders, or a message.
+ *
+ * @export
+ */
+export type Connection = ({ kind: "offer" } & PendingOffer) | ({ kind: "message" } & ReceivedMessage);
+
+
+/**
+ * Connects to the peer that generated the given Pylon code, and waits for their offer.
+ *
+ * If the offer is for an archive that was partially received before, accepting it resumes the
+ * earlier transfer. Messages aren't offered, but received right away.
+ *
+ * @export
+ * @async
+ * @param {string} code The Pylon code to connect with.
+ * @returns {Promise<Connection>} Resolves to the peer's offer or message, once it has been received.
+ */
+export async function connectReceive(code: string): Promise<Connection> {
+	return await invoke("connect_receive", { code });
+}
+
+
+/**
+ * Accepts the offer of the given session.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session returned by `connectReceive`.
+ * @param {string} [destinationDir] The directory to save the received files and folders in.
+ * Defaults to the download directory from the settings.
+ * @param {CollisionPolicy} [collisionPolicy] What to do with names that are already taken.
+ * Defaults to the policy from the settings.
+ * @returns {Promise<ReceiveResult>} Resolves once everything has been saved.
+ */
+export async function acceptOffer(id: SessionId, destinationDir?: string, collisionPolicy?: CollisionPolicy): Promise<ReceiveResult> {
+	return await invoke("accept_offer", { id, destinationDir, collisionPolicy });
+}
+
+
+/**
+ * Rejects the offer of the given session, letting the peer know.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session returned by `connectReceive`.
+ * @returns {Promise<void>} Resolves once the offer has been rejected.
+ */
+export async function rejectOffer(id: SessionId): Promise<void> {
+	return await invoke("reject_offer", { id });
+}
+
+
+/**
+ * A snapshot of a transfer's progress.
+ *
+ * @export
+ * @interface TransferProgress
+ */
+export interface TransferProgress {
+	id: SessionId;
+	direction: "send" | "receive";
+	bytesDone: number;
+	totalBytes: number;
+	/** Smoothed throughput, in bytes per second. */
+	throughput: number;
+	/** Estimated time remaining, in seconds. `null` until the throughput is known. */
+	eta: number | null;
+}
+
+
+/**
+ * Listens for transfer progress events emitted by the backend.
+ *
+ * @export
+ * @async
+ * @param {(progress: TransferProgress) => void} handler Called with each progress update.
+ * @returns {Promise<UnlistenFn>} Resolves to a function that stops listening.
+ */
+export async function onTransferProgress(handler: (progress: TransferProgress) => void): Promise<UnlistenFn> {
+	return await listen<TransferProgress>("transfer://progress", (event) => handler(event.payload));
+}
+
+
+/**
+ * Cancels the given transfer session.
+ *
+ * Pending calls for the session will reject with a cancellation error.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session to cancel.
+ * @returns {Promise<void>} Resolves once the session has been cancelled.
+ */
+export async function cancelTransfer(id: SessionId): Promise<void> {
+	return await invoke("cancel_transfer", { id });
+}
+
+
+/**
+ * Lists all transfer sessions.
+ *
+ * @export
+ * @async
+ * @returns {Promise<SessionInfo[]>} Resolves to the sessions, ordered by ID.
+ */
+export async function listSessions(): Promise<SessionInfo[]> {
+	return await invoke("list_sessions");
+}
+
+
+/**
+ * Describes the given transfer session.
+ *
+ * @export
+ * @async
+ * @param {SessionId} id The ID of the session to describe.
+ * @returns {Promise<SessionInfo>} Resolves to the session's description.
+ */
+export async function getSession(id: SessionId): Promise<SessionInfo> {
+	return await invoke("get_session", { id });
+}
+
+
+/**
+ * How much detail goes into the log files.
+ *
+ * @export
+ */
+export type LogLevel = "error" | "warn" | "info" | "debug" | "trace";
+
+
+/**
+ * User settings.
+ *
+ * @export
+ * @interface Settings
+ */
+export interface Settings {
+	theme: "system" | "light" | "dark";
+	language: string;
+	codeLength: number;
+	/** Where received files are saved. `null` means the OS's downloads directory. */
+	downloadDir: string | null;
+	collisionPolicy: CollisionPolicy;
+	/** The rendezvous server to use. `null` means Pylon's default server. */
+	rendezvousUrl: string | null;
+	/** The transit relays to use, in order of preference. */
+	relayUrls: string[];
+	/** Whether the public relay may be used, after the ones above. */
+	useDefaultRelay: boolean;
+	logLevel: LogLevel;
+}
+
+
+/**
+ * Returns the current settings.
+ *
+ * @export
+ * @async
+ * @returns {Promise<Settings>} Resolves to the current settings.
+ */
+export async function getSettings(): Promise<Settings> {
+	return await invoke("get_settings");
+}
+
+
+/**
+ * Updates some of the settings, and persists them.
+ *
+ * @export
+ * @async
+ * @param {Partial<Settings>} changes The settings to change.
+ * @returns {Promise<Settings>} Resolves to the updated settings.
+ */
+export async function updateSettings(changes: Partial<Settings>): Promise<Settings> {
+	return await invoke("update_settings", { changes });
+}
+
+
+/**
+ * The public transit relay. Keep in sync with `DEFAULT_RELAY_URL` in the backend.
+ *
+ * @export
+ */
+export const DEFAULT_RELAY_URL = "tcp://transit.magic-wormhole.io:4001";
+
+
+/**
+ * Whether a transit relay could be reached.
+ *
+ * @export
+ * @interface RelayStatus
+ */
+export interface RelayStatus {
+	url: string;
+	reachable: boolean;
+	/** How long it took to connect, in milliseconds. */
+	latency: number | null;
+	/** Why the relay couldn't be reached. */
+	error: string | null;
+}
+
+
+/**
+ * Checks whether each of the relays that would be used can be reached.
+ *
+ * @export
+ * @async
+ * @returns {Promise<RelayStatus[]>} Resolves to the status of each relay, in order of preference.
+ */
+export async function testRelays(): Promise<RelayStatus[]> {
+	return await invoke("test_relays");
+}
+
+
+/**
+ * A finished transfer, as recorded in the history.
+ *
+ * @export
+ * @interface HistoryEntry
+ */
+export interface HistoryEntry {
+	direction: "send" | "receive";
+	/** The names of the transferred top-level files and folders. */
+	names: string[];
+	/** The total size of the transferred files, in bytes. */
+	size: number;
+	bytesTransferred: number;
+	/** When the transfer started, in milliseconds since the Unix epoch. */
+	startedAt: number;
+	/** When the transfer finished, in milliseconds since the Unix epoch. */
+	finishedAt: number;
+	/** How long the transfer took, in milliseconds. */
+	duration: number;
+	outcome: "completed" | "failed" | "cancelled";
+	/** The hex-encoded SHA-256 digest of what was transferred, if it completed. */
+	digest: string | null;
+	/** The code of the error the transfer failed with, if it did. */
+	errorCode: string | null;
+}
+
+
+/**
+ * Lists every finished transfer.
+ *
+ * @export
+ * @async
+ * @returns {Promise<HistoryEntry[]>} Resolves to the transfers, most recent first.
+ */
+export async function listHistory(): Promise<HistoryEntry[]> {
+	return await invoke("list_history");
+}
+
+
+/**
+ * Removes every transfer from the history.
+ *
+ * @export
+ * @async
+ * @returns {Promise<void>} Resolves once the history has been cleared.
+ */
+export async function clearHistory(): Promise<void> {
+	return await invoke("clear_history");
+}
+
+
+/**
+ * Exports the history to a file, as a JSON array.
+ *
+ * @export
+ * @async
+ * @param {string} path The path of the file to export to.
+ * @returns {Promise<void>} Resolves once the history has been exported.
+ */
+export async function exportHistory(path: string): Promise<void> {
+	return await invoke("export_history", { path });
+}
+
+
+/**
+ * Opens the folder the log files are written in, with the OS's file manager.
+ *
+ * @export
+ * @async
+ * @returns {Promise<void>} Resolves once the folder has been opened.
+ */
+export async function openLogDir(): Promise<void> {
+	return await invoke("open_log_dir");
+}
+
+
+/**
+ * A request to start receiving, made by opening a `pylon://receive` link.
+ *
+ * @export
+ * @interface ReceiveLink
+ */
+export interface ReceiveLink {
+	code: string;
+}
+
+
+/**
+ * Takes the `pylon://receive` link the app was last opened with, if it hasn't been taken yet.
+ *
+ * @export
+ * @async
+ * @returns {Promise<ReceiveLink | null>} Resolves to the link, or `null` if there is none.
+ */
+export async function takePendingLink(): Promise<ReceiveLink | null> {
+	return await invoke("take_pending_link");
+}
+
+
+/**
+ * Listens for links opened with the app. Each one should be taken with `takePendingLink`.
+ *
+ * @export
+ * @async
+ * @param {() => void} handler Called whenever a link is waiting to be taken.
+ * @returns {Promise<UnlistenFn>} Resolves to a function that stops listening.
+ */
+export async function onLink(handler: () => void): Promise<UnlistenFn> {
+	return await listen("deep-link://received", () => handler());
 }
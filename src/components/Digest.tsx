@@ -0,0 +1,34 @@
+import { Snippet } from "@nextui-org/react";
+import { useTranslation } from "react-i18next";
+
+interface DigestProps {
+  digest: string;
+  verified?: boolean;
+}
+
+// Shows the digest of a completed transfer, ready to be copied.
+function Digest(props: DigestProps) {
+  const { t } = useTranslation();
+  const { digest, verified } = props;
+
+  return (
+    <div className="flex flex-col items-center space-y-1">
+      <span className="text-sm font-light text-foreground-500">
+        {verified === false
+          ? t("digest.unverifiedDescription")
+          : t("digest.description")}
+      </span>
+      <Snippet
+        symbol=""
+        size="sm"
+        className="font-mono max-w-full"
+        codeString={digest}
+        tooltipProps={{ content: t("digest.copyTooltip") }}
+      >
+        {`${digest.slice(0, 16)}…${digest.slice(-16)}`}
+      </Snippet>
+    </div>
+  );
+}
+
+export default Digest;
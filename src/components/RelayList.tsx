@@ -0,0 +1,196 @@
+import { Button, Chip, Input, Switch } from "@nextui-org/react";
+import {
+  TbArrowDown,
+  TbArrowUp,
+  TbPlugConnected,
+  TbPlus,
+  TbTrash,
+} from "react-icons/tb";
+import { useState } from "react";
+import { useTranslation } from "react-i18next";
+import * as bindings from "../bindings";
+
+interface RelayListProps {
+  relayUrls: string[];
+  useDefaultRelay: boolean;
+  onChange: (
+    changes: Pick<Partial<bindings.Settings>, "relayUrls" | "useDefaultRelay">
+  ) => Promise<void>;
+}
+
+function RelayList(props: RelayListProps) {
+  const { t } = useTranslation();
+  const { relayUrls, useDefaultRelay, onChange } = props;
+  const [newUrl, setNewUrl] = useState<string>("");
+  const [error, setError] = useState<string | null>(null);
+  const [statuses, setStatuses] = useState<bindings.RelayStatus[]>([]);
+  const [testing, setTesting] = useState<boolean>(false);
+
+  const change = function (
+    changes: Pick<Partial<bindings.Settings>, "relayUrls" | "useDefaultRelay">
+  ) {
+    return onChange(changes)
+      .then(() => {
+        setError(null);
+      })
+      .catch((err: unknown) => {
+        setError(bindings.errorMessage(t, err));
+        throw err;
+      });
+  };
+
+  const addHandler = function () {
+    const url = newUrl.trim();
+    if (url === "" || relayUrls.includes(url)) {
+      return;
+    }
+
+    change({ relayUrls: [...relayUrls, url] })
+      .then(() => {
+        setNewUrl("");
+      })
+      .catch(() => {});
+  };
+
+  const removeHandler = function (index: number) {
+    change({ relayUrls: relayUrls.filter((_, i) => i !== index) }).catch(
+      () => {}
+    );
+  };
+
+  // Swaps the relay at the given index with the one after it.
+  const swapHandler = function (index: number) {
+    const urls = [...relayUrls];
+    [urls[index], urls[index + 1]] = [urls[index + 1], urls[index]];
+    change({ relayUrls: urls }).catch(() => {});
+  };
+
+  const testHandler = function () {
+    setTesting(true);
+    bindings
+      .testRelays()
+      .then((statuses: bindings.RelayStatus[]) => {
+        setStatuses(statuses);
+      })
+      .catch((err: unknown) => {
+        setError(bindings.errorMessage(t, err));
+      })
+      .finally(() => {
+        setTesting(false);
+      });
+  };
+
+  const statusChip = function (url: string) {
+    const status = statuses.find((status) => status.url === url);
+    if (!status) {
+      return null;
+    }
+
+    return status.reachable ? (
+      <Chip color="success" size="sm" variant="flat">
+        {t("settings.relayReachable", { latency: status.latency })}
+      </Chip>
+    ) : (
+      <Chip color="danger" size="sm" variant="flat" title={status.error || ""}>
+        {t("settings.relayUnreachable")}
+      </Chip>
+    );
+  };
+
+  return (
+    <div className="flex flex-col gap-2">
+      <p className="text-small">{t("settings.relayListLabel")}</p>
+
+      {relayUrls.map((url, index) => (
+        <div key={url} className="flex items-center gap-1">
+          <span className="flex-grow truncate text-small" title={url}>
+            {url}
+          </span>
+          {statusChip(url)}
+          <Button
+            isIconOnly
+            size="sm"
+            variant="light"
+            isDisabled={index === 0}
+            aria-label={t("settings.relayMoveUpAriaLabel")}
+            onPress={() => swapHandler(index - 1)}
+          >
+            <TbArrowUp />
+          </Button>
+          <Button
+            isIconOnly
+            size="sm"
+            variant="light"
+            isDisabled={index === relayUrls.length - 1}
+            aria-label={t("settings.relayMoveDownAriaLabel")}
+            onPress={() => swapHandler(index)}
+          >
+            <TbArrowDown />
+          </Button>
+          <Button
+            isIconOnly
+            size="sm"
+            variant="light"
+            color="danger"
+            aria-label={t("settings.relayRemoveAriaLabel")}
+            onPress={() => removeHandler(index)}
+          >
+            <TbTrash />
+          </Button>
+        </div>
+      ))}
+
+      <Input
+        size="sm"
+        placeholder={t("settings.relayUrlInputPlaceholder")}
+        aria-label={t("settings.relayUrlInputAriaLabel")}
+        value={newUrl}
+        onValueChange={setNewUrl}
+        onKeyDown={(ev: React.KeyboardEvent) => {
+          if (ev.key === "Enter") {
+            addHandler();
+          }
+        }}
+        isInvalid={error !== null}
+        errorMessage={error}
+        endContent={
+          <Button
+            isIconOnly
+            size="sm"
+            variant="light"
+            aria-label={t("settings.relayAddAriaLabel")}
+            onPress={addHandler}
+          >
+            <TbPlus />
+          </Button>
+        }
+      />
+
+      <div className="flex items-center gap-1">
+        <Switch
+          size="sm"
+          isSelected={useDefaultRelay}
+          onValueChange={(useDefaultRelay: boolean) => {
+            change({ useDefaultRelay }).catch(() => {});
+          }}
+          className="flex-grow"
+        >
+          {t("settings.useDefaultRelayLabel")}
+        </Switch>
+        {useDefaultRelay && statusChip(bindings.DEFAULT_RELAY_URL)}
+      </div>
+
+      <Button
+        size="sm"
+        variant="flat"
+        isLoading={testing}
+        onPress={testHandler}
+        startContent={testing ? null : <TbPlugConnected />}
+      >
+        {t("settings.testRelaysButton")}
+      </Button>
+    </div>
+  );
+}
+
+export default RelayList;
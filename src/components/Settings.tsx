@@ -1,5 +1,6 @@
 import {
   Button,
+  Input,
   Modal,
   ModalContent,
   ModalHeader,
@@ -9,26 +10,147 @@ import {
   SelectItem,
   useDisclosure,
 } from "@nextui-org/react";
-import { TbLanguage, TbPaint, TbSettings } from "react-icons/tb";
+import {
+  TbCopy,
+  TbFileText,
+  TbFolder,
+  TbKey,
+  TbLanguage,
+  TbPaint,
+  TbServer,
+  TbSettings,
+  TbX,
+} from "react-icons/tb";
+import { useEffect, useState } from "react";
 import { useTranslation } from "react-i18next";
+import { open } from "@tauri-apps/api/dialog";
+import * as bindings from "../bindings";
+import RelayList from "./RelayList";
 
 export type Theme = "light" | "dark";
 export type ThemeChoice = "system" | "light" | "dark";
 export type Lang = "en" | "es" | "cn" | "de";
 
+// Keep in sync with `MIN_CODE_LENGTH` and `MAX_CODE_LENGTH` in the backend.
+const codeLengths = [2, 3, 4, 5, 6, 7, 8];
+
+const logLevels: bindings.LogLevel[] = [
+  "error",
+  "warn",
+  "info",
+  "debug",
+  "trace",
+];
+
 // TODO: docstring, once properties are finalized.
 interface SettingsProps {
   defaultThemeChoice?: ThemeChoice;
   onThemeChange?: (theme: React.ChangeEvent<HTMLSelectElement>) => void;
   defaultLang?: Lang;
   onLangChange?: (lang: React.ChangeEvent<HTMLSelectElement>) => void;
+  defaultCodeLength?: number;
+  onCodeLengthChange?: (
+    codeLength: React.ChangeEvent<HTMLSelectElement>
+  ) => void;
+  defaultCollisionPolicy?: bindings.CollisionPolicy;
+  onCollisionPolicyChange?: (
+    collisionPolicy: React.ChangeEvent<HTMLSelectElement>
+  ) => void;
+  downloadDir?: string | null;
+  onDownloadDirChange?: (downloadDir: string | null) => Promise<void>;
+  defaultRendezvousUrl?: string | null;
+  onRendezvousUrlChange?: (rendezvousUrl: string | null) => Promise<void>;
+  relayUrls?: string[];
+  useDefaultRelay?: boolean;
+  onRelaysChange?: (
+    changes: Pick<Partial<bindings.Settings>, "relayUrls" | "useDefaultRelay">
+  ) => Promise<void>;
+  defaultLogLevel?: bindings.LogLevel;
+  onLogLevelChange?: (logLevel: React.ChangeEvent<HTMLSelectElement>) => void;
 }
 
 function Settings(props: SettingsProps) {
   const { t } = useTranslation();
   const { isOpen, onOpen, onOpenChange } = useDisclosure();
-  const { defaultThemeChoice, onThemeChange, defaultLang, onLangChange } =
-    props;
+  const {
+    defaultThemeChoice,
+    onThemeChange,
+    defaultLang,
+    onLangChange,
+    defaultCodeLength,
+    onCodeLengthChange,
+    defaultCollisionPolicy,
+    onCollisionPolicyChange,
+    downloadDir,
+    onDownloadDirChange,
+    defaultRendezvousUrl,
+    onRendezvousUrlChange,
+    relayUrls,
+    useDefaultRelay,
+    onRelaysChange,
+    defaultLogLevel,
+    onLogLevelChange,
+  } = props;
+  const [rendezvousUrl, setRendezvousUrl] = useState<string>(
+    defaultRendezvousUrl || ""
+  );
+  const [rendezvousUrlError, setRendezvousUrlError] = useState<string | null>(
+    null
+  );
+  const [downloadDirError, setDownloadDirError] = useState<string | null>(
+    null
+  );
+
+  // The settings are loaded after we are first rendered.
+  useEffect(() => {
+    setRendezvousUrl(defaultRendezvousUrl || "");
+  }, [defaultRendezvousUrl]);
+
+  // No directory means the OS's downloads directory.
+  const downloadDirHandler = function (downloadDir: string | null) {
+    if (!onDownloadDirChange) {
+      return;
+    }
+
+    onDownloadDirChange(downloadDir)
+      .then(() => {
+        setDownloadDirError(null);
+      })
+      .catch((err: unknown) => {
+        setDownloadDirError(bindings.errorMessage(t, err));
+      });
+  };
+
+  const selectDownloadDirHandler = function () {
+    open({
+      title: t("settings.downloadDirDialogTitle"),
+      directory: true,
+      multiple: false,
+    })
+      .then((selected) => {
+        if (selected !== null && !Array.isArray(selected)) {
+          downloadDirHandler(selected);
+        }
+      })
+      .catch((err: Error) => {
+        console.error(err);
+      });
+  };
+
+  // An empty URL means the default server.
+  const rendezvousUrlBlurHandler = function () {
+    if (!onRendezvousUrlChange) {
+      return;
+    }
+
+    onRendezvousUrlChange(rendezvousUrl.trim() || null)
+      .then(() => {
+        setRendezvousUrlError(null);
+      })
+      .catch((err: unknown) => {
+        setRendezvousUrlError(bindings.errorMessage(t, err));
+      });
+  };
 
   return (
     <>
@@ -41,7 +163,6 @@ function Settings(props: SettingsProps) {
         <TbSettings />
       </Button>
 
-      {/* TODO: make settings persist between sessions */}
       <Modal
         isOpen={isOpen}
         onOpenChange={onOpenChange}
@@ -105,6 +226,141 @@ function Settings(props: SettingsProps) {
                     Deutsch
                   </SelectItem>
                 </Select>
+
+                {/* FIXME: disable color transition for start content */}
+                <Select
+                  label={t("settings.codeLengthSelectLabel")}
+                  description={t("settings.codeLengthDescription")}
+                  defaultSelectedKeys={[String(defaultCodeLength || 2)]}
+                  disallowEmptySelection
+                  className={"w-full"}
+                  aria-label={t("settings.codeLengthSelectAriaLabel")}
+                  onChange={onCodeLengthChange}
+                  startContent={<TbKey />}
+                >
+                  {codeLengths.map((length) => (
+                    <SelectItem key={String(length)} value={String(length)}>
+                      {String(length)}
+                    </SelectItem>
+                  ))}
+                </Select>
+
+                {/* FIXME: disable color transition for start content */}
+                <Input
+                  isReadOnly
+                  label={t("settings.downloadDirInputLabel")}
+                  placeholder={t("settings.downloadDirInputPlaceholder")}
+                  value={downloadDir || ""}
+                  isInvalid={downloadDirError !== null}
+                  errorMessage={downloadDirError}
+                  className={"w-full"}
+                  aria-label={t("settings.downloadDirInputAriaLabel")}
+                  startContent={<TbFolder />}
+                  endContent={
+                    <div className="flex flex-row">
+                      <Button
+                        isIconOnly
+                        size="sm"
+                        variant="light"
+                        aria-label={t("settings.downloadDirSelectAriaLabel")}
+                        onPress={selectDownloadDirHandler}
+                      >
+                        <TbFolder />
+                      </Button>
+                      {downloadDir && (
+                        <Button
+                          isIconOnly
+                          size="sm"
+                          variant="light"
+                          aria-label={t("settings.downloadDirResetAriaLabel")}
+                          onPress={() => downloadDirHandler(null)}
+                        >
+                          <TbX />
+                        </Button>
+                      )}
+                    </div>
+                  }
+                />
+
+                {/* FIXME: disable color transition for start content */}
+                <Select
+                  label={t("settings.collisionPolicySelectLabel")}
+                  defaultSelectedKeys={[defaultCollisionPolicy || "rename"]}
+                  disallowEmptySelection
+                  className={"w-full"}
+                  aria-label={t("settings.collisionPolicySelectAriaLabel")}
+                  onChange={onCollisionPolicyChange}
+                  startContent={<TbCopy />}
+                >
+                  <SelectItem
+                    key="rename"
+                    description={t("settings.collisionRenameDescription")}
+                  >
+                    {t("settings.collisionRename")}
+                  </SelectItem>
+                  <SelectItem key="overwrite">
+                    {t("settings.collisionOverwrite")}
+                  </SelectItem>
+                  <SelectItem key="skip">
+                    {t("settings.collisionSkip")}
+                  </SelectItem>
+                  <SelectItem key="ask">
+                    {t("settings.collisionAsk")}
+                  </SelectItem>
+                </Select>
+
+                {/* FIXME: disable color transition for start content */}
+                <Input
+                  label={t("settings.rendezvousUrlInputLabel")}
+                  placeholder={t("settings.rendezvousUrlInputPlaceholder")}
+                  description={t("settings.rendezvousUrlDescription")}
+                  value={rendezvousUrl}
+                  onValueChange={setRendezvousUrl}
+                  onBlur={rendezvousUrlBlurHandler}
+                  isInvalid={rendezvousUrlError !== null}
+                  errorMessage={rendezvousUrlError}
+                  className={"w-full"}
+                  aria-label={t("settings.rendezvousUrlInputAriaLabel")}
+                  startContent={<TbServer />}
+                />
+
+                {onRelaysChange && (
+                  <RelayList
+                    relayUrls={relayUrls || []}
+                    useDefaultRelay={useDefaultRelay ?? true}
+                    onChange={onRelaysChange}
+                  />
+                )}
+
+                {/* FIXME: disable color transition for start content */}
+                <Select
+                  label={t("settings.logLevelSelectLabel")}
+                  description={t("settings.logLevelDescription")}
+                  defaultSelectedKeys={[defaultLogLevel || "info"]}
+                  disallowEmptySelection
+                  className={"w-full"}
+                  aria-label={t("settings.logLevelSelectAriaLabel")}
+                  onChange={onLogLevelChange}
+                  startContent={<TbFileText />}
+                >
+                  {logLevels.map((level) => (
+                    <SelectItem key={level} value={level}>
+                      {t(`settings.logLevel_${level}`)}
+                    </SelectItem>
+                  ))}
+                </Select>
+
+                <Button
+                  variant="flat"
+                  startContent={<TbFolder />}
+                  onPress={() => {
+                    bindings.openLogDir().catch((err: Error) => {
+                      console.error(err);
+                    });
+                  }}
+                >
+                  {t("settings.openLogDirButton")}
+                </Button>
               </ModalBody>
 
               <ModalFooter>
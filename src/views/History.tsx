@@ -0,0 +1,140 @@
+import { Button, Chip, Spacer } from "@nextui-org/react";
+import { TbDownload, TbHistory, TbUpload } from "react-icons/tb";
+import { useTranslation } from "react-i18next";
+import { useEffect, useState } from "react";
+import { save } from "@tauri-apps/api/dialog";
+import * as bindings from "../bindings";
+
+const outcomeColors: Record<
+  bindings.HistoryEntry["outcome"],
+  "success" | "danger" | "default"
+> = {
+  completed: "success",
+  failed: "danger",
+  cancelled: "default",
+};
+
+function History() {
+  const { t } = useTranslation();
+  const [entries, setEntries] = useState<bindings.HistoryEntry[]>([]);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    bindings
+      .listHistory()
+      .then(setEntries)
+      .catch((err) => {
+        console.error(err);
+        setError(bindings.errorMessage(t, err));
+      });
+  }, []);
+
+  const exportHandler = async () => {
+    try {
+      const path = await save({
+        title: t("historyView.exportDialogTitle"),
+        defaultPath: "pylon-history.json",
+        filters: [{ name: "JSON", extensions: ["json"] }],
+      });
+
+      if (path !== null) {
+        await bindings.exportHistory(path);
+      }
+    } catch (err) {
+      console.error(err);
+      setError(bindings.errorMessage(t, err));
+    }
+  };
+
+  const clearHandler = async () => {
+    try {
+      await bindings.clearHistory();
+      setEntries([]);
+    } catch (err) {
+      console.error(err);
+      setError(bindings.errorMessage(t, err));
+    }
+  };
+
+  return (
+    <div className="flex flex-col items-center h-full space-y-1">
+      <TbHistory className="text-9xl text-foreground-500" />
+
+      <Spacer y={4} />
+
+      <span className="text-xl font-extrabold text-foreground">
+        {t("historyView.description")}
+      </span>
+
+      {error !== null && <span className="text-sm text-danger">{error}</span>}
+
+      <div className="flex flex-row space-x-2">
+        <Button
+          size="sm"
+          variant="flat"
+          onClick={exportHandler}
+          isDisabled={entries.length === 0}
+        >
+          {t("historyView.exportButtonLabel")}
+        </Button>
+        <Button
+          size="sm"
+          color="danger"
+          variant="flat"
+          onClick={clearHandler}
+          isDisabled={entries.length === 0}
+        >
+          {t("historyView.clearButtonLabel")}
+        </Button>
+      </div>
+
+      <Spacer y={2} />
+
+      {entries.length === 0 ? (
+        <span className="text-sm font-light text-foreground-500">
+          {t("historyView.empty")}
+        </span>
+      ) : (
+        <div className="flex flex-col w-4/5 space-y-2 overflow-y-auto">
+          {entries.map((entry) => (
+            <div
+              key={`${entry.startedAt}-${entry.names.join("/")}`}
+              className="flex flex-row items-center space-x-2"
+            >
+              {entry.direction === "send" ? (
+                <TbUpload aria-label={t("historyView.sent")} />
+              ) : (
+                <TbDownload aria-label={t("historyView.received")} />
+              )}
+              <div className="flex flex-col flex-grow min-w-0">
+                <span className="font-mono truncate">
+                  {entry.names.length > 0
+                    ? entry.names.join(", ")
+                    : t("historyView.message")}
+                </span>
+                <span className="text-xs font-light text-foreground-500">
+                  {t("historyView.summary", {
+                    date: new Date(entry.startedAt).toLocaleString(),
+                    size: entry.size,
+                    duration: Math.round(entry.duration / 1000),
+                  })}
+                </span>
+              </div>
+              <Chip
+                size="sm"
+                variant="flat"
+                color={outcomeColors[entry.outcome]}
+              >
+                {entry.errorCode !== null
+                  ? t(`errors.${entry.errorCode}`)
+                  : t(`historyView.${entry.outcome}`)}
+              </Chip>
+            </div>
+          ))}
+        </div>
+      )}
+    </div>
+  );
+}
+
+export default History;
@@ -1,9 +1,171 @@
-import { Button, Input, Spacer } from "@nextui-org/react";
-import { TbDownload } from "react-icons/tb";
+import {
+  Button,
+  Card,
+  CardBody,
+  CardFooter,
+  Input,
+  Spacer,
+  Textarea,
+} from "@nextui-org/react";
+import { TbCheck, TbCopy, TbDownload } from "react-icons/tb";
 import { useTranslation } from "react-i18next";
+import { useEffect, useState } from "react";
+import { open } from "@tauri-apps/api/dialog";
+import { writeText } from "@tauri-apps/api/clipboard";
+import * as bindings from "../bindings";
+import Digest from "../components/Digest";
 
-function Receive() {
+// The choices offered when some of the received names are already taken.
+const collisionPolicies: {
+  policy: bindings.CollisionPolicy;
+  label: string;
+}[] = [
+  { policy: "skip", label: "receiveView.collisionSkipButtonLabel" },
+  { policy: "overwrite", label: "receiveView.collisionOverwriteButtonLabel" },
+  { policy: "rename", label: "receiveView.collisionRenameButtonLabel" },
+];
+
+interface ReceiveProps {
+  link?: bindings.ReceiveLink | null;
+}
+
+function Receive(props: ReceiveProps) {
   const { t } = useTranslation();
+  const { link = null } = props;
+  const [code, setCode] = useState(link?.code ?? "");
+  const [isBusy, setIsBusy] = useState(false);
+  const [offer, setOffer] = useState<bindings.PendingOffer | null>(null);
+  const [message, setMessage] = useState<bindings.ReceivedMessage | null>(
+    null
+  );
+  const [isCopied, setIsCopied] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+  const [result, setResult] = useState<bindings.ReceiveResult | null>(null);
+  const [collision, setCollision] = useState<{
+    names: string[];
+    destinationDir?: string;
+  } | null>(null);
+
+  // Fill in the code of a newly opened link, but leave starting to the user.
+  useEffect(() => {
+    if (link !== null) {
+      setCode(link.code);
+    }
+  }, [link]);
+
+  const receiveHandler = async () => {
+    setIsBusy(true);
+    setError(null);
+    setResult(null);
+
+    try {
+      const connection = await bindings.connectReceive(code.trim());
+      if (connection.kind === "message") {
+        setMessage(connection);
+        setIsCopied(false);
+        setCode("");
+      } else {
+        setOffer(connection);
+      }
+    } catch (err) {
+      console.error(err);
+      setError(bindings.errorMessage(t, err));
+    }
+
+    setIsBusy(false);
+  };
+
+  const acceptHandler = async (
+    destinationDir?: string,
+    collisionPolicy?: bindings.CollisionPolicy
+  ) => {
+    if (offer === null) {
+      return;
+    }
+
+    setIsBusy(true);
+    setError(null);
+
+    try {
+      const result = await bindings.acceptOffer(
+        offer.id,
+        destinationDir,
+        collisionPolicy
+      );
+      console.log(result);
+      setResult(result);
+      setCode("");
+    } catch (err) {
+      // The offer is left pending, until we decide what to do with taken names.
+      if (bindings.isCommandError(err) && err.code === "name_collision") {
+        setCollision({
+          names: err.details?.names as string[],
+          destinationDir,
+        });
+        setIsBusy(false);
+        return;
+      }
+
+      console.error(err);
+      setError(bindings.errorMessage(t, err));
+
+      // Another destination may have enough room, so leave the offer pending.
+      if (bindings.isCommandError(err) && err.code === "insufficient_space") {
+        setIsBusy(false);
+        return;
+      }
+    }
+
+    setOffer(null);
+    setCollision(null);
+    setIsBusy(false);
+  };
+
+  // Saves somewhere other than the download directory, just this once.
+  const saveAsHandler = async () => {
+    try {
+      const selected = await open({
+        title: t("receiveView.selectDestinationDialogTitle"),
+        directory: true,
+        multiple: false,
+      });
+
+      if (selected !== null && !Array.isArray(selected)) {
+        await acceptHandler(selected);
+      }
+    } catch (err) {
+      console.error(err);
+    }
+  };
+
+  // The message is only ever copied to the clipboard, never saved.
+  const copyHandler = async () => {
+    if (message === null) {
+      return;
+    }
+
+    try {
+      await writeText(message.text);
+      setIsCopied(true);
+    } catch (err) {
+      console.error(err);
+    }
+  };
+
+  const rejectHandler = async () => {
+    if (offer === null) {
+      return;
+    }
+
+    try {
+      await bindings.rejectOffer(offer.id);
+    } catch (err) {
+      console.error(err);
+    }
+
+    setOffer(null);
+    setCollision(null);
+  };
 
   return (
     <div className="flex flex-col justify-center items-center h-full space-y-1">
@@ -14,22 +176,156 @@ function Receive() {
       <span className="text-xl font-extrabold text-foreground">
         {t("receiveView.description")}
       </span>
-      <span className="text-sm font-light text-foreground-500">
-        {t("receiveView.instruction")}
-      </span>
 
-      <Spacer y={4} />
+      {error !== null && <span className="text-sm text-danger">{error}</span>}
+
+      {result !== null && result.digest !== null && (
+        <Digest digest={result.digest} verified={result.verified} />
+      )}
+
+      {message !== null ? (
+        <Card
+          shadow="none"
+          className="bg-background/60 dark:bg-default-100/50 w-3/5"
+        >
+          <CardBody className="flex flex-col justify-center items-center">
+            <Textarea
+              isReadOnly
+              label={t("receiveView.messageDescription")}
+              value={message.text}
+            />
+          </CardBody>
+
+          <CardFooter className="flex flex-row justify-center space-x-2">
+            <Button
+              color="primary"
+              variant="flat"
+              startContent={isCopied ? <TbCheck /> : <TbCopy />}
+              onClick={copyHandler}
+            >
+              {isCopied
+                ? t("receiveView.copiedButtonLabel")
+                : t("receiveView.copyButtonLabel")}
+            </Button>
+            <Button color="primary" onClick={() => setMessage(null)}>
+              {t("receiveView.dismissButtonLabel")}
+            </Button>
+          </CardFooter>
+        </Card>
+      ) : offer === null ? (
+        <>
+          <span className="text-sm font-light text-foreground-500">
+            {t("receiveView.instruction")}
+          </span>
+
+          <Spacer y={4} />
+
+          <div className="flex flex-row space-x-2 w-3/5">
+            <Input
+              label={t("receiveView.pylonCodeInputLabel")}
+              size="sm"
+              className="font-mono"
+              value={code}
+              onValueChange={setCode}
+              isDisabled={isBusy}
+            />
+            <Button
+              color="primary"
+              className="self-center"
+              onClick={receiveHandler}
+              isDisabled={code.trim() === ""}
+              isLoading={isBusy}
+            >
+              {t("receiveView.receiveButtonLabel")}
+            </Button>
+          </div>
+        </>
+      ) : (
+        <Card
+          shadow="none"
+          className="bg-background/60 dark:bg-default-100/50 w-3/5"
+        >
+          <CardBody className="flex flex-col justify-center items-center">
+            <span className="text-sm font-light text-foreground-500">
+              {t("receiveView.offerSummary", {
+                count: offer.entryCount,
+                size: offer.size,
+              })}
+            </span>
+            {offer.resumedBytes > 0 && (
+              <span className="text-sm font-light text-foreground-500">
+                {t("receiveView.resumeDescription", {
+                  size: offer.resumedBytes,
+                })}
+              </span>
+            )}
+            {offer.names.map((name) => (
+              <span key={name} className="font-mono">
+                {name}
+              </span>
+            ))}
+          </CardBody>
 
-      <div className="flex flex-row space-x-2 w-3/5">
-        <Input
-          label={t("receiveView.pylonCodeInputLabel")}
-          size="sm"
-          className="font-mono"
-        />
-        <Button color="primary" className="self-center">
-          {t("receiveView.receiveButtonLabel")}
-        </Button>
-      </div>
+          {collision === null ? (
+            <CardFooter className="flex flex-row justify-center space-x-2">
+              <Button
+                color="danger"
+                variant="flat"
+                onClick={rejectHandler}
+                isDisabled={isBusy}
+              >
+                {t("receiveView.rejectButtonLabel")}
+              </Button>
+              <Button
+                color="primary"
+                variant="flat"
+                onClick={saveAsHandler}
+                isDisabled={isBusy}
+              >
+                {t("receiveView.saveAsButtonLabel")}
+              </Button>
+              <Button
+                color="primary"
+                onClick={() => acceptHandler()}
+                isLoading={isBusy}
+              >
+                {t("receiveView.acceptButtonLabel")}
+              </Button>
+            </CardFooter>
+          ) : (
+            <CardFooter className="flex flex-col items-center space-y-2">
+              <span className="text-sm text-warning">
+                {t("receiveView.collisionDescription", {
+                  names: collision.names.join(", "),
+                })}
+              </span>
+              <div className="flex flex-row justify-center space-x-2">
+                <Button
+                  color="danger"
+                  variant="flat"
+                  onClick={rejectHandler}
+                  isDisabled={isBusy}
+                >
+                  {t("receiveView.rejectButtonLabel")}
+                </Button>
+                {collisionPolicies.map(({ policy, label }) => (
+                  <Button
+                    key={policy}
+                    color="primary"
+                    variant={policy === "rename" ? "solid" : "flat"}
+                    onClick={() =>
+                      acceptHandler(collision.destinationDir, policy)
+                    }
+                    isDisabled={isBusy}
+                  >
+                    {t(label)}
+                  </Button>
+                ))}
+              </div>
+            </CardFooter>
+          )}
+        </Card>
+      )}
     </div>
   );
 }
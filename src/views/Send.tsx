@@ -1,48 +1,131 @@
 import { Spacer } from "@nextui-org/react";
 import { useTranslation } from "react-i18next";
-import { ReactNode, useState } from "react";
+import { ReactNode, useRef, useState } from "react";
 import { TbUpload } from "react-icons/tb";
 import { open } from "@tauri-apps/api/dialog";
 import * as bindings from "../bindings";
 import Send_SelectView from "./Send_SelectView";
 import Send_GenView from "./Send_GenView";
 import Send_CodeView from "./Send_CodeView";
+import Send_TextView from "./Send_TextView";
+import Digest from "../components/Digest";
 
-function Send() {
+interface SendProps {
+  codeLength?: number;
+}
+
+function Send(props: SendProps) {
   const { t } = useTranslation();
+  const { codeLength } = props;
+
+  // The current view holds on to the handlers it was created with, so read the code length
+  // through a ref to always get the latest one.
+  const codeLengthRef = useRef(codeLength);
+  codeLengthRef.current = codeLength;
+
+  // The session being sent with, so that cancelling leaves any others alone.
+  const sessionIdRef = useRef<bindings.SessionId | null>(null);
+
+  const [error, setError] = useState<string | null>(null);
+  const [digest, setDigest] = useState<string | null>(null);
+
+  const cancelHandler = async () => {
+    const id = sessionIdRef.current;
+    sessionIdRef.current = null;
+
+    if (id !== null) {
+      try {
+        await bindings.cancelTransfer(id);
+      } catch (err) {
+        console.error(err);
+      }
+    }
+
+    setCurrentView(selectView());
+  };
+
+  // The session is created first, so that it can be cancelled while its code is generated.
+  const genCode = async () => {
+    const id = await bindings.createSendSession();
+    sessionIdRef.current = id;
+    const code = await bindings.genCode(id, codeLengthRef.current);
+
+    return { id, code };
+  };
+
+  const sendFiles = async (paths: string[]) => {
+    const { id, code } = await genCode();
+    setCurrentView(
+      <Send_CodeView id={id} code={code} cancelHandler={cancelHandler} />
+    );
+
+    const result = await bindings.sendFile(id, paths);
+    console.log(result);
+    setDigest(result.digest);
 
-  // FIXME: hitting cancel in the gen view will sometimes still show the code view.
-  const cancelHandler = () => {
-    setCurrentView(<Send_SelectView selectFileHandler={selectFileHandler} />);
+    setCurrentView(selectView());
   };
 
-  const getCode = async () => {
-    // TODO: parameterize code length.
+  // Messages go through the same code flow as files.
+  const sendText = async (text: string) => {
+    setCurrentView(<Send_GenView cancelHandler={cancelHandler} />);
+
     try {
-      const code = await bindings.genCode(2);
-      console.log(code);
+      const { id, code } = await genCode();
       setCurrentView(
-        <Send_CodeView code={code} cancelHandler={cancelHandler} />
+        <Send_CodeView id={id} code={code} cancelHandler={cancelHandler} />
       );
-    } catch (e) {
-      console.error(e);
+
+      const result = await bindings.sendText(id, text);
+      console.log(result);
+      setDigest(result.digest);
+
+      setCurrentView(selectView());
+    } catch (err) {
+      console.error(err);
+
+      // Cancellation already resets the view.
+      if (!bindings.isCommandError(err) || err.code !== "cancelled") {
+        setError(bindings.errorMessage(t, err));
+        setCurrentView(selectView());
+      }
     }
   };
 
-  const selectFileHandler = async () => {
+  const selectTextHandler = () => {
+    setError(null);
+    setDigest(null);
+    setCurrentView(
+      <Send_TextView
+        sendHandler={sendText}
+        cancelHandler={() => setCurrentView(selectView())}
+      />
+    );
+  };
+
+  const selectHandler = async (directory: boolean) => {
     try {
       const selected = await open({
-        title: "Select file",
-        multiple: false,
+        title: directory ? "Select folder" : "Select files",
+        directory,
+        multiple: !directory,
       });
 
-      if (selected !== null) {
+      if (selected !== null && selected.length > 0) {
+        setError(null);
+        setDigest(null);
         setCurrentView(<Send_GenView cancelHandler={cancelHandler} />);
 
         try {
-          await getCode();
+          await sendFiles(Array.isArray(selected) ? selected : [selected]);
         } catch (err) {
           console.error(err);
+
+          // Cancellation already resets the view, so there's nothing more to do for it.
+          if (!bindings.isCommandError(err) || err.code !== "cancelled") {
+            setError(bindings.errorMessage(t, err));
+            setCurrentView(selectView());
+          }
         }
       }
     } catch (err) {
@@ -50,11 +133,17 @@ function Send() {
     }
   };
 
-  type Views = ReactNode;
-  const [currentView, setCurrentView] = useState<Views>(
-    <Send_SelectView selectFileHandler={selectFileHandler} />
+  const selectView = () => (
+    <Send_SelectView
+      selectFileHandler={() => selectHandler(false)}
+      selectFolderHandler={() => selectHandler(true)}
+      selectTextHandler={selectTextHandler}
+    />
   );
 
+  type Views = ReactNode;
+  const [currentView, setCurrentView] = useState<Views>(selectView());
+
   return (
     <div className="flex flex-col justify-center items-center h-full space-y-1">
       <TbUpload className="text-9xl text-foreground-500" />
@@ -65,6 +154,10 @@ function Send() {
         {t("sendView.description")}
       </span>
 
+      {error !== null && <span className="text-sm text-danger">{error}</span>}
+
+      {digest !== null && <Digest digest={digest} />}
+
       {currentView}
     </div>
   );
@@ -1,14 +1,37 @@
-import { Button, Card, CardBody, CardFooter, Snippet } from "@nextui-org/react";
+import {
+  Button,
+  Card,
+  CardBody,
+  CardFooter,
+  Image,
+  Snippet,
+} from "@nextui-org/react";
 import { useTranslation } from "react-i18next";
+import { useEffect, useState } from "react";
+import * as bindings from "../bindings";
 
 interface Send_CodeViewProps {
+  id: bindings.SessionId;
   code: string;
   cancelHandler: () => void;
 }
 
 function Send_CodeView(props: Send_CodeViewProps) {
   const { t } = useTranslation();
-  const { code, cancelHandler } = props;
+  const { id, code, cancelHandler } = props;
+  const [qrCode, setQrCode] = useState<string | null>(null);
+
+  // Scanning the QR code opens the app, ready to receive.
+  useEffect(() => {
+    bindings
+      .codeQr(id, true)
+      .then((svg) => {
+        setQrCode(`data:image/svg+xml;utf8,${encodeURIComponent(svg)}`);
+      })
+      .catch((err: Error) => {
+        console.error(err);
+      });
+  }, [id]);
 
   return (
     <Card
@@ -17,7 +40,16 @@ function Send_CodeView(props: Send_CodeViewProps) {
     >
       {/* TODO: add header with file information */}
 
-      <CardBody className="flex flex-col justify-center items-center">
+      <CardBody className="flex flex-row justify-center items-center space-x-4">
+        {qrCode !== null && (
+          <Image
+            src={qrCode}
+            alt={t("sendView.qrCodeAlt")}
+            width={128}
+            height={128}
+            radius="sm"
+          />
+        )}
         <Snippet symbol="" color="success" variant="flat">
           {code}
         </Snippet>
@@ -6,17 +6,19 @@ import {
   DropdownTrigger,
   Spacer,
 } from "@nextui-org/react";
-import { TbChevronDown, TbFile, TbFolder } from "react-icons/tb";
+import { TbChevronDown, TbFile, TbFolder, TbMessage } from "react-icons/tb";
 import { useTranslation } from "react-i18next";
 import { Key } from "react";
 
 interface Send_SelectViewProps {
   selectFileHandler: () => void;
+  selectFolderHandler: () => void;
+  selectTextHandler: () => void;
 }
 
 function Send_SelectView(props: Send_SelectViewProps) {
   const { t } = useTranslation();
-  const { selectFileHandler } = props;
+  const { selectFileHandler, selectFolderHandler, selectTextHandler } = props;
 
   const handler = (key: Key) => {
     switch (key) {
@@ -24,7 +26,11 @@ function Send_SelectView(props: Send_SelectViewProps) {
         selectFileHandler();
         break;
       case "folder":
-        return;
+        selectFolderHandler();
+        break;
+      case "text":
+        selectTextHandler();
+        break;
     }
   };
 
@@ -44,7 +50,6 @@ function Send_SelectView(props: Send_SelectViewProps) {
         </DropdownTrigger>
 
         <DropdownMenu
-          disabledKeys={["folder"]}
           onAction={handler}
           aria-label={t("sendSelectView.selectDropdownAriaLabel")}
         >
@@ -52,13 +57,13 @@ function Send_SelectView(props: Send_SelectViewProps) {
             {t("sendSelectView.selectFileLabel")}
           </DropdownItem>
 
-          <DropdownItem
-            key="folder"
-            startContent={<TbFolder />}
-            description={t("sendSelectView.selectFolderDescription")}
-          >
+          <DropdownItem key="folder" startContent={<TbFolder />}>
             {t("sendSelectView.selectFolderLabel")}
           </DropdownItem>
+
+          <DropdownItem key="text" startContent={<TbMessage />}>
+            {t("sendSelectView.selectTextLabel")}
+          </DropdownItem>
         </DropdownMenu>
       </Dropdown>
     </>
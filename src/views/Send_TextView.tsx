@@ -0,0 +1,81 @@
+import { Button, Spacer, Textarea } from "@nextui-org/react";
+import { TbClipboard } from "react-icons/tb";
+import { useTranslation } from "react-i18next";
+import { useState } from "react";
+import { readText } from "@tauri-apps/api/clipboard";
+import * as bindings from "../bindings";
+
+interface Send_TextViewProps {
+  sendHandler: (text: string) => void;
+  cancelHandler: () => void;
+}
+
+function Send_TextView(props: Send_TextViewProps) {
+  const { t } = useTranslation();
+  const { sendHandler, cancelHandler } = props;
+  const [text, setText] = useState("");
+
+  // The limit is in bytes, not characters.
+  const size = new TextEncoder().encode(text).length;
+  const isTooLong = size > bindings.MAX_MESSAGE_SIZE;
+
+  const pasteHandler = async () => {
+    try {
+      const pasted = await readText();
+      if (pasted !== null) {
+        setText(pasted);
+      }
+    } catch (err) {
+      console.error(err);
+    }
+  };
+
+  return (
+    <>
+      <span className="text-sm font-light text-foreground-500">
+        {t("sendTextView.instruction")}
+      </span>
+
+      <Spacer y={4} />
+
+      <Textarea
+        label={t("sendTextView.textInputLabel")}
+        className="w-3/5"
+        value={text}
+        onValueChange={setText}
+        isInvalid={isTooLong}
+        errorMessage={
+          isTooLong &&
+          t("errors.text_too_long", {
+            size,
+            max: bindings.MAX_MESSAGE_SIZE,
+          })
+        }
+      />
+
+      <Spacer y={2} />
+
+      <div className="flex flex-row space-x-2">
+        <Button color="danger" variant="flat" onClick={cancelHandler}>
+          {t("sendView.cancelButtonLabel")}
+        </Button>
+        <Button
+          variant="flat"
+          startContent={<TbClipboard />}
+          onClick={pasteHandler}
+        >
+          {t("sendTextView.pasteButtonLabel")}
+        </Button>
+        <Button
+          color="primary"
+          onClick={() => sendHandler(text)}
+          isDisabled={text === "" || isTooLong}
+        >
+          {t("sendTextView.sendButtonLabel")}
+        </Button>
+      </div>
+    </>
+  );
+}
+
+export default Send_TextView;